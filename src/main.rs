@@ -54,15 +54,101 @@ enum Commands {
     Compile {
         /// Input .lxi file
         input: String,
-        /// Target language
-        #[arg(short, long, default_value = "javascript")]
-        target: String,
+        /// Target language (default: frontmatter, project defaultTarget, then javascript)
+        #[arg(short, long)]
+        target: Option<String>,
+        /// Profile to generate with instead of the active one
+        #[arg(long)]
+        profile: Option<String>,
+        /// Model to generate with
+        #[arg(long)]
+        model: Option<String>,
+        /// Sampling temperature
+        #[arg(long)]
+        temperature: Option<f32>,
+        /// Maximum tokens in the response
+        #[arg(long)]
+        max_tokens: Option<u32>,
+        /// SQL schema (DDL) given to the model; with sql:sqlite the output is checked against it
+        #[arg(long)]
+        schema: Option<String>,
+        /// Write and run output even if it contains destructive commands such as `rm -rf /`
+        #[arg(long)]
+        allow_dangerous: bool,
         /// Output file
         #[arg(short, long)]
         output: Option<String>,
         /// Compile and run immediately
         #[arg(short, long)]
         run: bool,
+        /// Save stdout/stderr of the run to log files next to the output
+        #[arg(long, requires = "run")]
+        capture: bool,
+        /// Skip checking `Example:` lines against the generated code
+        #[arg(long)]
+        no_examples: bool,
+        /// Replace the output even if it has been edited by hand
+        #[arg(long)]
+        overwrite: bool,
+        /// Write <output>.map.json mapping .lxi blocks to the generated lines
+        #[arg(long)]
+        source_map: bool,
+        /// Annotate generated code with comments pointing at its .lxi source lines
+        #[arg(long)]
+        inline_refs: bool,
+        /// Arguments passed to the program when running (after --)
+        #[arg(last = true, requires = "run")]
+        args: Vec<String>,
+    },
+    /// Generate and run unit tests from .test.lxi descriptions
+    Test {
+        /// .test.lxi files to run (default: all in the project's source directory)
+        files: Vec<String>,
+        /// Target language of the compiled output under test
+        #[arg(short, long)]
+        target: Option<String>,
+        /// JUnit XML report path (default: <buildDir>/test-results.xml)
+        #[arg(long)]
+        report: Option<String>,
+    },
+    /// Recompile .lxi files in the source directory as they change
+    Watch {
+        /// Target language
+        #[arg(short, long)]
+        target: Option<String>,
+        /// Run each output after it is recompiled
+        #[arg(short, long)]
+        run: bool,
+        /// Milliseconds a file must stay unchanged before it is recompiled
+        #[arg(long, default_value_t = 300)]
+        debounce: u64,
+    },
+    /// Describe existing source code as a .lxi program
+    Decompile {
+        /// Source file to describe
+        input: String,
+        /// Output .lxi file (default: <name>.lxi next to the input)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Language of the input (default: inferred from the file extension)
+        #[arg(short, long)]
+        target: Option<String>,
+    },
+    /// Change generated code with a follow-up instruction
+    Refine {
+        /// Generated file to change
+        file: String,
+        /// What to change, in English
+        instruction: String,
+        /// Target language (default: inferred from the file extension)
+        #[arg(short, long)]
+        target: Option<String>,
+        /// Apply the changes without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+        /// Apply changes that add destructive commands such as `rm -rf /`
+        #[arg(long)]
+        allow_dangerous: bool,
     },
     /// Create new Lexi project
     Init {
@@ -78,6 +164,25 @@ enum Commands {
         #[command(subcommand)]
         profile_command: ProfileCommands,
     },
+    /// List supported target languages
+    Targets,
+    /// Inspect the prompts sent to the model
+    Prompt {
+        #[command(subcommand)]
+        prompt_command: PromptCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum PromptCommands {
+    /// Print the exact prompt a compile would send for a .lxi file
+    Show {
+        /// Input .lxi file
+        file: String,
+        /// Target language (default: frontmatter, project defaultTarget, then javascript)
+        #[arg(short, long)]
+        target: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -113,6 +218,451 @@ impl Default for Config {
     }
 }
 
+/// Project settings read from lexi.config.json in the working directory
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+struct ProjectConfig {
+    default_target: Option<String>,
+    source_dir: String,
+    build_dir: String,
+    /// House conventions per target
+    style: HashMap<String, StyleGuide>,
+    /// Approved description/code pairs, in one subdirectory per target
+    examples_dir: String,
+    /// Extra targets, or changes to built-in ones
+    targets: HashMap<String, Target>,
+    mongodb: MongoModel,
+    redis: RedisModel,
+}
+
+/// Collections the mongodb target may use
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct MongoModel {
+    /// Sample document or JSON schema per collection, inline or as a path to a .json file
+    collections: HashMap<String, serde_json::Value>,
+}
+
+/// Keys the redis target may use
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct RedisModel {
+    keys: Vec<RedisKey>,
+}
+
+/// A Redis key pattern such as `user:{id}` and the type stored under it
+#[derive(Deserialize, Clone)]
+struct RedisKey {
+    pattern: String,
+    #[serde(rename = "type")]
+    key_type: String,
+    /// Hash fields, when the type is hash
+    #[serde(default)]
+    fields: Vec<String>,
+    #[serde(default)]
+    description: Option<String>,
+}
+
+/// What the output of a data-store target may reference
+enum DataModel {
+    /// Field paths with their types, per collection
+    Mongo(Vec<(String, Vec<(String, String)>)>),
+    Redis(Vec<RedisKey>),
+}
+
+/// Everything Lexi knows about one target language
+#[derive(Deserialize, Default, Clone)]
+#[serde(rename_all = "camelCase", default)]
+struct Target {
+    /// Set from the registry key
+    #[serde(skip)]
+    name: String,
+    aliases: Vec<String>,
+    /// Output file extension, including the dot
+    extension: String,
+    /// Markdown fence tags stripped from responses
+    fences: Vec<String>,
+    /// Line comment marker
+    comment: String,
+    /// Extra rules for the system prompt
+    prompt: Option<String>,
+    /// Commands that check a file without running it, each run when installed; the file path is appended
+    /// unless they use {file}
+    validators: Vec<String>,
+    /// Patterns of destructive code that is only written with --allow-dangerous
+    denylist: Vec<String>,
+    /// Commands that run a file, tried in order until one is installed; the file path is appended
+    /// unless they use {file}
+    runners: Vec<String>,
+    /// Output that is only written and checked, never run, such as configuration or patterns
+    declarative: bool,
+    /// How the output is laid out on disk
+    #[serde(skip)]
+    layout: OutputLayout,
+    /// Text that starts a function definition, with {name} for the function's name; signatures are
+    /// only checked when it is set
+    #[serde(skip)]
+    definition_pattern: Option<String>,
+    /// How signatures and declared types are written; type declarations need it
+    #[serde(skip)]
+    type_syntax: Option<TypeSyntax>,
+    /// How .lxi types are spelled in signatures
+    #[serde(skip)]
+    type_spellings: TypeSpellings,
+    /// How generated modules refer to each other; @import needs it
+    #[serde(skip)]
+    module_wiring: Option<ModuleWiring>,
+    /// Interpreter that checks `Example:` lines
+    #[serde(skip)]
+    example_runner: Option<ExampleRunner>,
+    /// Native unit test framework used by `lexi test`
+    #[serde(skip)]
+    test_framework: Option<TestRunner>,
+    /// Well-formedness and required keys checked before the validators run
+    #[serde(skip)]
+    structure: Option<ConfigFormat>,
+    /// Toolchain check that needs a generated project around the file
+    #[serde(skip)]
+    build_check: Option<BuildCheck>,
+    #[serde(skip)]
+    custom: bool,
+}
+
+/// How a target's output is laid out on disk
+#[derive(Clone, Copy, Default, PartialEq)]
+enum OutputLayout {
+    /// The generated code as one file
+    #[default]
+    File,
+    /// index.html with style.css and app.js next to it, in the build directory
+    WebBundle,
+    /// A Jupyter notebook with a markdown and a code cell per block
+    Notebook,
+    /// One checked pattern per block, written in the given flavor
+    Patterns(RegexFlavor),
+}
+
+/// Regex syntax a pattern target is written for
+#[derive(Clone, Copy, PartialEq)]
+enum RegexFlavor {
+    Rust,
+    Pcre,
+    JavaScript,
+    Python,
+}
+
+/// Language whose syntax signatures and declared types are rendered and parsed in
+#[derive(Clone, Copy, PartialEq)]
+enum TypeSyntax {
+    Python,
+    TypeScript,
+    /// JSDoc typedefs, untyped signatures
+    JavaScript,
+    Rust,
+    Go,
+    Java,
+    Cpp,
+}
+
+/// Spellings of .lxi types in a target's signatures; the first of each is used in prompts, and a type
+/// without spellings is not checked
+#[derive(Clone, Default)]
+struct TypeSpellings {
+    /// Spellings of `string`, `int`, `float`, `bool` and `void`; an empty spelling is a left-out type
+    builtin: HashMap<String, Vec<String>>,
+    /// Templates for `list<T>`, with {T} for each spelling of the item type
+    list: Vec<String>,
+    /// Templates for `map<K, V>`, with {K} and {V}
+    map: Vec<String>,
+    /// Templates for declared and other named types, with {T} for the name
+    named: Vec<String>,
+}
+
+/// How generated modules refer to each other
+#[derive(Clone, Default)]
+struct ModuleWiring {
+    /// Rule for the importer, with {module} for the module name and {file} for its output file
+    import: String,
+    /// Rule for the imported module
+    export: String,
+}
+
+/// Interpreter the example harness is written for
+#[derive(Clone, Copy)]
+enum ExampleRunner {
+    Node,
+    Python,
+}
+
+/// Native unit test frameworks `lexi test` can generate tests for
+#[derive(Clone, Copy)]
+enum TestRunner {
+    Jest,
+    Pytest,
+    RustTest,
+    GoTest,
+    JUnit,
+}
+
+/// Configuration formats with a built-in structure check
+#[derive(Clone, Copy, PartialEq)]
+enum ConfigFormat {
+    Dockerfile,
+    Compose,
+    Kubernetes,
+    Terraform,
+}
+
+/// Toolchain checks Lexi sets up itself rather than running as a validator command, run when the tool is installed
+#[derive(Clone, Copy)]
+enum BuildCheck {
+    /// `terraform validate` after an init without a backend or modules
+    Terraform,
+    /// `dotnet build` of a temporary .csproj
+    Dotnet,
+    /// `config --quiet` with the Compose v2 plugin, or the standalone v1 binary
+    Compose,
+}
+
+impl BuildCheck {
+    fn program(self) -> &'static str {
+        match self {
+            BuildCheck::Terraform => "terraform",
+            BuildCheck::Dotnet => "dotnet",
+            BuildCheck::Compose => "docker compose",
+        }
+    }
+}
+
+/// Conventions for one target, given to the model and checked after generation
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct StyleGuide {
+    /// Conventions written inline
+    guide: Option<String>,
+    /// Markdown file with conventions, relative to the project root
+    file: Option<String>,
+    rules: Vec<StyleRule>,
+}
+
+/// A pattern the generated code must not contain (`forbid`) or must contain (`require`)
+#[derive(Deserialize, Clone)]
+struct StyleRule {
+    forbid: Option<String>,
+    require: Option<String>,
+    message: String,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        ProjectConfig {
+            default_target: None,
+            source_dir: "src".to_string(),
+            build_dir: "build".to_string(),
+            style: HashMap::new(),
+            examples_dir: "examples".to_string(),
+            targets: HashMap::new(),
+            mongodb: MongoModel::default(),
+            redis: RedisModel::default(),
+        }
+    }
+}
+
+/// An approved pair from the examples directory, offered to the model as a few-shot example
+struct FewShotExample {
+    description: String,
+    code: String,
+}
+
+/// Flags that shape a single compile
+struct CompileOptions {
+    check_examples: bool,
+    overwrite: bool,
+    source_map: bool,
+    inline_refs: bool,
+    overrides: ModelOverrides,
+    /// SQL schema (DDL) file given on the command line
+    schema: Option<String>,
+    /// Write output that matches the target's denylist of destructive commands
+    allow_dangerous: bool,
+}
+
+/// Model settings that take precedence over the profile's
+#[derive(Default, Clone)]
+struct ModelOverrides {
+    profile: Option<String>,
+    model: Option<String>,
+    temperature: Option<f32>,
+    max_tokens: Option<u32>,
+}
+
+impl ModelOverrides {
+    // Settings set here win over those in `fallback`
+    fn or(&self, fallback: &ModelOverrides) -> ModelOverrides {
+        ModelOverrides {
+            profile: self.profile.clone().or_else(|| fallback.profile.clone()),
+            model: self.model.clone().or_else(|| fallback.model.clone()),
+            temperature: self.temperature.or(fallback.temperature),
+            max_tokens: self.max_tokens.or(fallback.max_tokens),
+        }
+    }
+}
+
+/// Settings from a `---` header at the top of a .lxi file
+#[derive(Default)]
+struct Frontmatter {
+    target: Option<String>,
+    overrides: ModelOverrides,
+    style: Option<String>,
+    schema: Option<String>,
+}
+
+/// A .lxi source being compiled, with the extra prompt context it needs
+struct CompileJob<'a> {
+    input: &'a str,
+    /// Source without its frontmatter; the header lines are left blank to keep line numbers
+    lexi_content: String,
+    target: &'a str,
+    overrides: ModelOverrides,
+    /// Project style guide and frontmatter style notes, given to the model
+    style: Option<String>,
+    style_rules: Vec<StyleRule>,
+    /// SQL schema (DDL) the output runs against
+    schema: Option<String>,
+    /// Declared collections or keys for the mongodb and redis targets
+    data_model: Option<DataModel>,
+    /// Directory the output is written to; imports and example harnesses resolve against it
+    output_dir: &'a std::path::Path,
+    context: String,
+    /// Declared types rendered for the target, attached to every generation
+    type_definitions: Option<String>,
+}
+
+/// How compiled output is executed when `--run` is given
+struct RunOptions {
+    args: Vec<String>,
+    capture: bool,
+}
+
+/// An `Example: call -> expected` line inside a .lxi block
+struct LexiExample {
+    call: String,
+    expected: String,
+    line: usize,
+}
+
+/// A `fn name(param: type) -> type` line pinning a function's interface
+struct LexiSignature {
+    name: String,
+    params: Vec<(String, String)>,
+    returns: String,
+    text: String,
+}
+
+/// A `type Name { field: type, optional?: type }` declaration
+struct LexiType {
+    name: String,
+    fields: Vec<LexiField>,
+}
+
+struct LexiField {
+    name: String,
+    lexi_type: String,
+    optional: bool,
+}
+
+/// A `Should match:` or `Should not match:` line, checked against a regex target's pattern
+struct RegexCase {
+    text: String,
+    should_match: bool,
+    line: usize,
+}
+
+/// A paragraph of a .lxi file; blank lines separate blocks
+struct LexiBlock {
+    start_line: usize,
+    end_line: usize,
+    text: String,
+    examples: Vec<LexiExample>,
+    signatures: Vec<LexiSignature>,
+    regex_cases: Vec<RegexCase>,
+}
+
+/// An example that did not hold for the generated code
+struct ExampleFailure<'a> {
+    block: &'a LexiBlock,
+    example: &'a LexiExample,
+    detail: String,
+}
+
+/// Scratch files and directories removed when dropped, even if a check bails out early
+struct ScratchPaths(Vec<PathBuf>);
+
+impl Drop for ScratchPaths {
+    fn drop(&mut self) {
+        for path in &self.0 {
+            let _ = if path.is_dir() { fs::remove_dir_all(path) } else { fs::remove_file(path) };
+        }
+    }
+}
+
+/// Native unit test framework used by `lexi test` for a target
+struct TestFramework {
+    name: &'static str,
+    test_file: String,
+    /// How the generated tests should reach the code under test
+    import_rule: String,
+    /// Generated tests are appended to the module source instead of importing it
+    inline: bool,
+    /// Commands run in the build directory, in order; all must succeed
+    commands: Vec<Vec<String>>,
+    /// Matches one test in the output, capturing its name as `pass` or `fail`
+    case_pattern: &'static str,
+}
+
+/// Outcome of one .test.lxi file
+struct TestResult {
+    file: String,
+    framework: &'static str,
+    passed: bool,
+    /// Individual tests found in the output, with whether each passed
+    cases: Vec<(String, bool)>,
+    output: String,
+    duration: std::time::Duration,
+}
+
+/// Sidecar written by `--source-map`, tracing generated lines back to .lxi blocks
+#[derive(Serialize)]
+struct SourceMap {
+    version: u32,
+    source: String,
+    output: String,
+    mappings: Vec<BlockMapping>,
+}
+
+#[derive(Serialize)]
+struct BlockMapping {
+    block: usize,
+    source: SourceSpan,
+    output: OutputSpan,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SourceSpan {
+    start_line: usize,
+    end_line: usize,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct OutputSpan {
+    start_line: usize,
+    end_line: usize,
+    start_byte: usize,
+    end_byte: usize,
+}
+
 #[derive(Serialize)]
 struct OpenAIRequest {
     model: String,
@@ -173,15 +723,88 @@ struct OllamaResponse {
 
 struct LexiCompiler {
     config_path: PathBuf,
+    /// lexi.config.json, read once per command
+    project: ProjectConfig,
+    /// Built-in targets with the project's additions and changes applied
+    targets: Vec<Target>,
 }
 
+/// Generations tried (including the first) before giving up on failing checks
+const MAX_GENERATION_ATTEMPTS: u32 = 3;
+
+/// Most few-shot examples included in one prompt
+const MAX_FEW_SHOT_EXAMPLES: usize = 3;
+
+/// Similarity below which a few-shot example is not considered relevant
+const MIN_FEW_SHOT_SIMILARITY: f64 = 0.2;
+
+/// Files of a `web` bundle with their fence tags, in generation order
+const WEB_FILES: [(&str, &str); 3] = [("index.html", "html"), ("style.css", "css"), ("app.js", "javascript")];
+
+/// How long an example harness may run before it is killed
+const EXAMPLE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// How long node or python3 may take to run a target's regex cases before it is killed
+const REGEX_ENGINE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Redis commands that only work on keys of one type, by that type
+const REDIS_COMMAND_TYPES: [(&str, &[&str]); 6] = [
+    (
+        "string",
+        &[
+            "GET", "SET", "SETEX", "PSETEX", "SETNX", "GETSET", "GETDEL", "GETEX", "INCR", "INCRBY", "INCRBYFLOAT", "DECR", "DECRBY",
+            "APPEND", "STRLEN", "GETRANGE", "SETRANGE", "MGET", "MSET", "MSETNX",
+        ],
+    ),
+    (
+        "list",
+        &[
+            "LPUSH", "RPUSH", "LPUSHX", "RPUSHX", "LPOP", "RPOP", "LRANGE", "LLEN", "LINDEX", "LSET", "LREM", "LTRIM", "LINSERT", "LPOS",
+            "BLPOP", "BRPOP", "RPOPLPUSH", "BRPOPLPUSH", "LMOVE", "BLMOVE",
+        ],
+    ),
+    (
+        "set",
+        &[
+            "SADD", "SREM", "SMEMBERS", "SISMEMBER", "SMISMEMBER", "SCARD", "SPOP", "SRANDMEMBER", "SSCAN", "SMOVE", "SINTER", "SUNION",
+            "SDIFF", "SINTERSTORE", "SUNIONSTORE", "SDIFFSTORE",
+        ],
+    ),
+    (
+        "hash",
+        &[
+            "HSET", "HSETNX", "HMSET", "HGET", "HMGET", "HGETALL", "HDEL", "HEXISTS", "HKEYS", "HVALS", "HLEN", "HSTRLEN", "HINCRBY",
+            "HINCRBYFLOAT", "HSCAN", "HRANDFIELD", "HGETDEL", "HGETEX", "HSETEX", "HEXPIRE", "HPEXPIRE", "HEXPIREAT", "HPEXPIREAT",
+            "HEXPIRETIME", "HPEXPIRETIME", "HTTL", "HPTTL", "HPERSIST",
+        ],
+    ),
+    (
+        "zset",
+        &[
+            "ZADD", "ZREM", "ZSCORE", "ZMSCORE", "ZINCRBY", "ZCARD", "ZCOUNT", "ZLEXCOUNT", "ZRANGE", "ZRANGEBYSCORE", "ZRANGEBYLEX",
+            "ZREVRANGE", "ZREVRANGEBYSCORE", "ZREVRANGEBYLEX", "ZRANK", "ZREVRANK", "ZPOPMIN", "ZPOPMAX", "BZPOPMIN", "BZPOPMAX",
+            "ZRANDMEMBER", "ZREMRANGEBYRANK", "ZREMRANGEBYSCORE", "ZREMRANGEBYLEX", "ZSCAN", "ZRANGESTORE",
+        ],
+    ),
+    (
+        "stream",
+        &["XADD", "XRANGE", "XREVRANGE", "XLEN", "XDEL", "XTRIM", "XACK", "XCLAIM", "XAUTOCLAIM", "XPENDING", "XSETID"],
+    ),
+];
+
 impl LexiCompiler {
-    fn new() -> Self {
+    fn new() -> Result<Self, Box<dyn std::error::Error>> {
+        LexiCompiler::with_project(LexiCompiler::load_project_config()?)
+    }
+
+    fn with_project(project: ProjectConfig) -> Result<Self, Box<dyn std::error::Error>> {
         let mut config_path = dirs::home_dir().expect("Could not find home directory");
         config_path.push(".lexi");
         config_path.push("config.json");
         
-        LexiCompiler { config_path }
+        let mut compiler = LexiCompiler { config_path, project, targets: Vec::new() };
+        compiler.targets = compiler.load_targets()?;
+        Ok(compiler)
     }
 
     fn load_config(&self) -> Config {
@@ -213,6 +836,466 @@ impl LexiCompiler {
         }
     }
 
+    // Only a missing lexi.config.json means defaults; a broken one is reported with its line and column
+    fn load_project_config() -> Result<ProjectConfig, Box<dyn std::error::Error>> {
+        match fs::read_to_string("lexi.config.json") {
+            Ok(content) => serde_json::from_str(&content).map_err(|error| format!("Invalid lexi.config.json: {}", error).into()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(ProjectConfig::default()),
+            Err(error) => Err(format!("Failed to read lexi.config.json: {}", error).into()),
+        }
+    }
+
+    fn project_target(&self, project: &ProjectConfig, target: Option<&str>) -> Result<String, Box<dyn std::error::Error>> {
+        let name = target
+            .map(str::to_string)
+            .or_else(|| project.default_target.clone())
+            .unwrap_or_else(|| "javascript".to_string());
+        self.canonical_target(&name)
+    }
+
+    // Resolves an alias to its target name
+    fn canonical_target(&self, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(self.target(name)?.name.clone())
+    }
+
+    // Looks up a target by name or alias
+    fn target(&self, name: &str) -> Result<&Target, Box<dyn std::error::Error>> {
+        let name = name.to_lowercase();
+        self.targets
+            .iter()
+            .find(|target| target.name == name || target.aliases.contains(&name))
+            .ok_or_else(|| format!("Unknown target '{}'. Run `lexi targets` to see the supported ones", name).into())
+    }
+
+    fn layout(&self, target: &str) -> OutputLayout {
+        self.target(target).map(|target| target.layout).unwrap_or_default()
+    }
+
+    // Built-in targets with the project's additions and changes applied
+    fn load_targets(&self) -> Result<Vec<Target>, Box<dyn std::error::Error>> {
+        let mut targets = self.builtin_targets();
+        let mut custom: Vec<(String, Target)> = self.project.targets.clone().into_iter().collect();
+        custom.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (name, overrides) in custom {
+            let name = name.to_lowercase();
+            let Some(target) = targets.iter_mut().find(|target| target.name == name) else {
+                // Output files are named and recognized by their extension
+                if overrides.extension.is_empty() {
+                    return Err(format!("Target '{}' in lexi.config.json has no extension, e.g. \".lua\"", name).into());
+                }
+                targets.push(Target {
+                    name,
+                    comment: if overrides.comment.is_empty() { "//".to_string() } else { overrides.comment.clone() },
+                    custom: true,
+                    ..overrides
+                });
+                continue;
+            };
+
+            // Only the fields the project sets replace the built-in ones
+            target.aliases.extend(overrides.aliases);
+            if !overrides.extension.is_empty() {
+                target.extension = overrides.extension;
+            }
+            if !overrides.fences.is_empty() {
+                target.fences = overrides.fences;
+            }
+            if !overrides.comment.is_empty() {
+                target.comment = overrides.comment;
+            }
+            if overrides.prompt.is_some() {
+                target.prompt = overrides.prompt;
+            }
+            if !overrides.validators.is_empty() {
+                target.validators = overrides.validators;
+            }
+            target.denylist.extend(overrides.denylist);
+            if !overrides.runners.is_empty() {
+                target.runners = overrides.runners;
+            }
+            target.declarative |= overrides.declarative;
+        }
+        Ok(targets)
+    }
+
+    fn builtin_targets(&self) -> Vec<Target> {
+        let strings = |items: &[&str]| items.iter().map(|item| item.to_string()).collect::<Vec<_>>();
+        let target = |name: &str, aliases: &[&str], extension: &str, fences: &[&str], comment: &str| Target {
+            name: name.to_string(),
+            aliases: strings(aliases),
+            extension: extension.to_string(),
+            fences: strings(fences),
+            comment: comment.to_string(),
+            ..Target::default()
+        };
+        let wiring = |import: &str, export: &str| Some(ModuleWiring { import: import.to_string(), export: export.to_string() });
+        let spellings = |builtin: &[(&str, &[&str])], list: &[&str], map: &[&str], named: &[&str]| TypeSpellings {
+            builtin: builtin.iter().map(|(name, spelled)| (name.to_string(), strings(spelled))).collect(),
+            list: strings(list),
+            map: strings(map),
+            named: strings(named),
+        };
+
+        // Commands that wipe or brick a machine
+        let shell_denylist = strings(&[
+            // Recursive rm, with its flags in any order, on the root, home or a variable that may be empty followed by `/`
+            r#"\brm\s+(?:-\S+\s+)*(?:-[A-Za-z]*[rR][A-Za-z]*|--recursive)\s+(?:-\S+\s+)*(?:/\*?|~/?\*?|"?\$\{?HOME\}?"?/?\*?|"?\$\{?\w+\}?"?/"?\*?|"\$\{?\w+\}?/\*?"\*?)(?:\s|;|&|\||$)"#,
+            r"--no-preserve-root",
+            r"\bmkfs(?:\.\w+)?\b",
+            r"\bdd\b[^\n]*\bof=/dev/(?:sd|hd|vd|xvd|nvme|disk|mmcblk)",
+            r">\s*/dev/(?:sd|hd|vd|xvd|nvme|disk|mmcblk)\w*",
+            r":\(\)\s*\{\s*:\s*\|\s*:\s*&\s*\}\s*;\s*:",
+            r"\bchmod\s+(?:-R\s+)?[0-7]*777\s+/(?:\s|$)",
+            r"\bchown\s+-R\s+\S+\s+/(?:\s|$)",
+            r"\bfind\s+/\s[^\n]*-delete\b",
+            r"\b(?:curl|wget)\b[^|\n]*\|\s*(?:sudo\s+)?(?:ba|z)?sh\b",
+        ]);
+
+        vec![
+            Target {
+                runners: strings(&["node"]),
+                definition_pattern: Some("function {name}(".to_string()),
+                type_syntax: Some(TypeSyntax::JavaScript),
+                module_wiring: wiring(
+                    "Load it with `const { ... } = require('./{file}')`",
+                    "Export every top-level function and class with module.exports = { ... }",
+                ),
+                example_runner: Some(ExampleRunner::Node),
+                test_framework: Some(TestRunner::Jest),
+                ..target("javascript", &["js", "node"], ".js", &["javascript", "js"], "//")
+            },
+            Target {
+                prompt: Some(
+                    "The code must compile under `tsc --strict` with no errors. Give every parameter, return value and exported binding an explicit type, and never use `any` (use `unknown` and narrow it). Prefer interfaces and type aliases for data shapes, and union types over loose strings."
+                        .to_string(),
+                ),
+                validators: strings(&["tsc --noEmit --strict --skipLibCheck --target es2020 --module commonjs"]),
+                runners: strings(&["tsx", "ts-node"]),
+                definition_pattern: Some("function {name}(".to_string()),
+                type_syntax: Some(TypeSyntax::TypeScript),
+                type_spellings: spellings(
+                    &[("string", &["string"]), ("int", &["number"]), ("float", &["number"]), ("bool", &["boolean"]), ("void", &["void"])],
+                    &["{T}[]", "Array<{T}>"],
+                    &["Record<{K}, {V}>", "Map<{K}, {V}>"],
+                    &["{T}"],
+                ),
+                module_wiring: wiring(
+                    "Import it with `import { ... } from './{module}'`",
+                    "Export every top-level function, class, interface and type alias other files use with `export`",
+                ),
+                ..target("typescript", &["ts"], ".ts", &["typescript", "ts"], "//")
+            },
+            Target {
+                runners: strings(&["python", "python3"]),
+                definition_pattern: Some("def {name}(".to_string()),
+                type_syntax: Some(TypeSyntax::Python),
+                type_spellings: spellings(
+                    &[("string", &["str"]), ("int", &["int"]), ("float", &["float"]), ("bool", &["bool"]), ("void", &["None", ""])],
+                    &["list[{T}]", "List[{T}]"],
+                    &["dict[{K}, {V}]", "Dict[{K}, {V}]"],
+                    &["{T}"],
+                ),
+                module_wiring: wiring("Import it with `from {module} import ...`", "Put any script code under `if __name__ == \"__main__\":`"),
+                example_runner: Some(ExampleRunner::Python),
+                test_framework: Some(TestRunner::Pytest),
+                ..target("python", &["py"], ".py", &["python", "py"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Start with `#!/usr/bin/env bash` and `set -euo pipefail`. Quote every expansion, use [[ ]] for tests, check required commands and arguments up front, and send errors to stderr with a non-zero exit. Never run rm or other destructive commands on a path that could be empty or /."
+                        .to_string(),
+                ),
+                validators: strings(&["bash -n", "shellcheck -f gcc -S warning"]),
+                runners: strings(&["bash"]),
+                denylist: shell_denylist.clone(),
+                ..target("bash", &["shell"], ".sh", &["bash", "shell", "sh"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Write POSIX sh with no bashisms. Start with `#!/bin/sh` and `set -eu`. Quote every expansion, use [ ] for tests, check required commands and arguments up front, and send errors to stderr with a non-zero exit. Never run rm or other destructive commands on a path that could be empty or /."
+                        .to_string(),
+                ),
+                validators: strings(&["sh -n", "shellcheck -s sh -f gcc -S warning"]),
+                runners: strings(&["sh"]),
+                denylist: shell_denylist,
+                ..target("sh", &["posix"], ".sh", &["sh", "shell"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Target C# 14 on .NET 10; the file runs as a file-based app with `dotnet run app.cs`. Use top-level statements for the entry point, file-scoped namespaces, nullable reference types, records for data and async/await for I/O. Keep the program in this single file, and add NuGet packages only with `#:package Name@Version` directives at the top."
+                        .to_string(),
+                ),
+                // `--` keeps the program's arguments away from dotnet run's own options
+                runners: strings(&["dotnet run {file} --"]),
+                build_check: Some(BuildCheck::Dotnet),
+                ..target("csharp", &["cs", "c#", "dotnet"], ".cs", &["csharp", "cs", "c#"], "//")
+            },
+            Target {
+                prompt: Some(
+                    "Write idiomatic Kotlin for the JVM with a top-level `fun main()`. Use data classes, val over var, null safety without `!!`, and the standard library collection functions."
+                        .to_string(),
+                ),
+                validators: strings(&["kotlinc {file} -d {build}"]),
+                runners: strings(&["kotlinc {file} -include-runtime -d {build}/{stem}.jar && java -jar {build}/{stem}.jar"]),
+                ..target("kotlin", &["kt"], ".kt", &["kotlin", "kt"], "//")
+            },
+            Target {
+                prompt: Some(
+                    "Write idiomatic Ruby 3. Start with `# frozen_string_literal: true`, use snake_case methods, keyword arguments, blocks and Enumerable, and raise specific error classes."
+                        .to_string(),
+                ),
+                validators: strings(&["ruby -wc"]),
+                runners: strings(&["ruby"]),
+                ..target("ruby", &["rb"], ".rb", &["ruby", "rb"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Write PHP 8.2. Start with `<?php` and `declare(strict_types=1);`, type every parameter, property and return value, and throw exceptions for errors. Leave out the closing `?>` tag."
+                        .to_string(),
+                ),
+                validators: strings(&["php -l"]),
+                runners: strings(&["php"]),
+                ..target("php", &[], ".php", &["php"], "//")
+            },
+            Target {
+                prompt: Some(
+                    "Write Swift 5.9 as a single script with top-level code for the entry point. Prefer structs and enums over classes, unwrap optionals with guard or if let, and use throwing functions for errors."
+                        .to_string(),
+                ),
+                validators: strings(&["swiftc -typecheck"]),
+                runners: strings(&["swift"]),
+                ..target("swift", &[], ".swift", &["swift"], "//")
+            },
+            Target {
+                definition_pattern: Some("{name}(".to_string()),
+                type_syntax: Some(TypeSyntax::Java),
+                type_spellings: spellings(
+                    &[
+                        ("string", &["String"]),
+                        ("int", &["int", "long", "Integer", "Long"]),
+                        ("float", &["double", "float", "Double", "Float"]),
+                        ("bool", &["boolean", "Boolean"]),
+                        ("void", &["void"]),
+                    ],
+                    &["List<{T}>", "{T}[]", "ArrayList<{T}>"],
+                    &["Map<{K}, {V}>", "HashMap<{K}, {V}>"],
+                    &["{T}"],
+                ),
+                module_wiring: wiring(
+                    "It is in the same default package; call its public static methods through its class name",
+                    "Make the class and the methods other files use public static, with no main method",
+                ),
+                test_framework: Some(TestRunner::JUnit),
+                ..target("java", &[], ".java", &["java"], "//")
+            },
+            Target {
+                definition_pattern: Some("{name}(".to_string()),
+                type_syntax: Some(TypeSyntax::Cpp),
+                type_spellings: spellings(
+                    &[
+                        ("string", &["std::string", "const std::string&", "std::string&", "string", "const string&"]),
+                        ("int", &["int", "long", "long long", "size_t"]),
+                        ("float", &["double", "float"]),
+                        ("bool", &["bool"]),
+                        ("void", &["void"]),
+                    ],
+                    &["std::vector<{T}>", "const std::vector<{T}>&", "std::vector<{T}>&", "vector<{T}>"],
+                    &["std::map<{K}, {V}>", "const std::map<{K}, {V}>&", "std::unordered_map<{K}, {V}>", "const std::unordered_map<{K}, {V}>&"],
+                    &["{T}", "const {T}&", "{T}&"],
+                ),
+                module_wiring: wiring("Include it with `#include \"{file}\"`", "Do not write a main function"),
+                ..target("cpp", &["c++", "cxx"], ".cpp", &["cpp", "c++"], "//")
+            },
+            Target {
+                definition_pattern: Some("fn {name}(".to_string()),
+                type_syntax: Some(TypeSyntax::Rust),
+                type_spellings: spellings(
+                    &[
+                        ("string", &["&str", "String", "&String"]),
+                        ("int", &["i64", "i32", "usize", "isize", "u32", "u64"]),
+                        ("float", &["f64", "f32"]),
+                        ("bool", &["bool"]),
+                        ("void", &["", "()"]),
+                    ],
+                    &["Vec<{T}>", "&[{T}]", "&Vec<{T}>"],
+                    &["HashMap<{K}, {V}>", "&HashMap<{K}, {V}>", "BTreeMap<{K}, {V}>"],
+                    &["{T}", "&{T}"],
+                ),
+                module_wiring: wiring(
+                    "Declare it with `mod {module};` and bring names in with `use {module}::...;`",
+                    "Mark every item other modules use `pub` and do not write a main function",
+                ),
+                test_framework: Some(TestRunner::RustTest),
+                ..target("rust", &["rs"], ".rs", &["rust", "rs"], "//")
+            },
+            Target {
+                definition_pattern: Some("func {name}(".to_string()),
+                type_syntax: Some(TypeSyntax::Go),
+                type_spellings: spellings(
+                    &[
+                        ("string", &["string"]),
+                        ("int", &["int", "int64", "int32"]),
+                        ("float", &["float64", "float32"]),
+                        ("bool", &["bool"]),
+                        ("void", &[""]),
+                    ],
+                    &["[]{T}"],
+                    &["map[{K}]{V}"],
+                    &["{T}"],
+                ),
+                module_wiring: wiring(
+                    "It is in the same package; call its functions directly without an import",
+                    "Use package main and do not write a main function",
+                ),
+                test_framework: Some(TestRunner::GoTest),
+                ..target("go", &["golang"], ".go", &["go", "golang"], "//")
+            },
+            Target {
+                prompt: Some("Use standard SQL that works across major databases (PostgreSQL, MySQL, SQL Server).".to_string()),
+                ..target("sql", &[], ".sql", &["sql"], "--")
+            },
+            Target {
+                prompt: Some(
+                    "Write PostgreSQL. Use its features where they help: RETURNING, ILIKE, JSONB operators, window functions, ON CONFLICT upserts, double-quoted identifiers and $1-style parameters."
+                        .to_string(),
+                ),
+                ..target("sql:postgres", &["postgres", "postgresql", "sql:postgresql"], ".sql", &["sql", "postgresql", "postgres", "pgsql"], "--")
+            },
+            Target {
+                prompt: Some(
+                    "Write MySQL 8. Use backtick-quoted identifiers, INSERT ... ON DUPLICATE KEY UPDATE for upserts, LIMIT for paging and ? parameters."
+                        .to_string(),
+                ),
+                ..target("sql:mysql", &["mysql", "mariadb"], ".sql", &["sql", "mysql"], "--")
+            },
+            Target {
+                prompt: Some(
+                    "Write SQLite. Respect its type affinity, use INSERT ... ON CONFLICT for upserts, date() and strftime() for dates, and ? parameters. Avoid features SQLite lacks, such as stored procedures and ALTER COLUMN."
+                        .to_string(),
+                ),
+                ..target("sql:sqlite", &["sqlite", "sqlite3"], ".sql", &["sql", "sqlite"], "--")
+            },
+            Target {
+                prompt: Some(
+                    "Write T-SQL for SQL Server. Use TOP or OFFSET ... FETCH instead of LIMIT, square-bracket identifiers, MERGE for upserts and @name parameters."
+                        .to_string(),
+                ),
+                ..target("sql:mssql", &["mssql", "tsql", "sqlserver"], ".sql", &["sql", "tsql"], "--")
+            },
+            Target {
+                layout: OutputLayout::WebBundle,
+                ..target("web", &["html"], ".html", &["html", "css", "javascript", "js"], "//")
+            },
+            Target {
+                runners: strings(&["jupyter nbconvert --to notebook --execute --output {stem}.executed {file}"]),
+                layout: OutputLayout::Notebook,
+                example_runner: Some(ExampleRunner::Python),
+                ..target("jupyter", &["notebook", "ipynb"], ".ipynb", &["python", "py"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Write a production Dockerfile. Pin base image tags (never `latest`), use a multi-stage build when there is a build step, copy dependency manifests before the source for layer caching, combine RUN steps and clean package caches, run as a non-root USER and use exec-form CMD and ENTRYPOINT. Never embed secrets."
+                        .to_string(),
+                ),
+                validators: strings(&["hadolint --failure-threshold warning"]),
+                declarative: true,
+                structure: Some(ConfigFormat::Dockerfile),
+                ..target("dockerfile", &["docker"], ".Dockerfile", &["dockerfile", "docker"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Write a Compose file with a top-level `services` map and no obsolete `version` key. Give every service an `image` or `build`, pin image tags, use named volumes for data, add health checks and `depends_on` conditions where start order matters, and take credentials from environment variables or secrets."
+                        .to_string(),
+                ),
+                declarative: true,
+                structure: Some(ConfigFormat::Compose),
+                build_check: Some(BuildCheck::Compose),
+                ..target("compose", &["docker-compose"], ".compose.yaml", &["yaml", "yml"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Write Terraform 1.x HCL. Declare `required_providers` with version constraints in a `terraform` block, give every variable a type and description, expose useful values as outputs, tag resources where the provider supports it and never hard-code credentials."
+                        .to_string(),
+                ),
+                declarative: true,
+                structure: Some(ConfigFormat::Terraform),
+                build_check: Some(BuildCheck::Terraform),
+                ..target("terraform", &["tf", "hcl"], ".tf", &["terraform", "hcl", "tf"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "Write Kubernetes manifests as YAML documents separated by `---`, using stable apiVersions such as apps/v1 and networking.k8s.io/v1. Every object has apiVersion, kind and metadata.name. Workloads pin image tags, set resource requests and limits, liveness and readiness probes and a non-root securityContext, and their selectors match the pod template labels."
+                        .to_string(),
+                ),
+                validators: strings(&["kubeconform -strict"]),
+                declarative: true,
+                structure: Some(ConfigFormat::Kubernetes),
+                ..target("kubernetes", &["k8s", "kube"], ".yaml", &["yaml", "yml"], "#")
+            },
+            Target {
+                prompt: Some("The patterns are used with Rust's regex crate.".to_string()),
+                declarative: true,
+                layout: OutputLayout::Patterns(RegexFlavor::Rust),
+                ..target("regex", &["regexp"], ".regex", &["regex", "text"], "#")
+            },
+            Target {
+                prompt: Some("The patterns are used with PCRE2, as in grep -P, PHP or nginx. Name groups (?<name>...).".to_string()),
+                declarative: true,
+                layout: OutputLayout::Patterns(RegexFlavor::Pcre),
+                ..target("regex:pcre", &["pcre"], ".regex", &["regex", "text"], "#")
+            },
+            Target {
+                prompt: Some(
+                    "The patterns become JavaScript RegExp literals. Put flags only in a leading group such as (?i), and name groups (?<name>...)."
+                        .to_string(),
+                ),
+                declarative: true,
+                layout: OutputLayout::Patterns(RegexFlavor::JavaScript),
+                ..target("regex:js", &["regex:javascript"], ".js", &["regex", "text", "javascript", "js"], "//")
+            },
+            Target {
+                prompt: Some("The patterns are compiled with Python's re module. Name groups (?P<name>...).".to_string()),
+                declarative: true,
+                layout: OutputLayout::Patterns(RegexFlavor::Python),
+                ..target("regex:python", &["regex:py"], ".py", &["regex", "text", "python", "py"], "#")
+            },
+            target("mongodb", &["mongo"], ".js", &["javascript", "js"], "//"),
+            target("redis", &[], ".txt", &["redis"], "#"),
+        ]
+    }
+
+    fn list_targets(&self) {
+        println!("🎯 Supported targets:");
+        for target in &self.targets {
+            let mut details = vec![target.extension.clone()];
+            if !target.aliases.is_empty() {
+                details.push(format!("aliases: {}", target.aliases.join(", ")));
+            }
+            let mut checkers: Vec<&str> = target.build_check.iter().map(|check| check.program()).collect();
+            checkers.extend(target.validators.iter().filter_map(|validator| validator.split_whitespace().next()));
+            if !checkers.is_empty() {
+                details.push(format!("checked by: {}", checkers.join(", ")));
+            }
+            if !target.runners.is_empty() {
+                let programs: Vec<String> = target.runners.iter().map(|runner| self.runner_programs(runner)).collect();
+                details.push(format!("runs with: {}", programs.join(" or ")));
+            }
+            let origin = if target.custom { " (project)" } else { "" };
+            println!("   • {}{} - {}", target.name, origin, details.join(" | "));
+        }
+    }
+
+    // The programs a runner needs, e.g. `kotlinc + java`
+    fn runner_programs(&self, runner: &str) -> String {
+        runner.split("&&").filter_map(|step| step.split_whitespace().next()).collect::<Vec<_>>().join(" + ")
+    }
+
+    fn file_target(&self, cli_target: Option<&str>, lexi_content: &str, project: &ProjectConfig) -> Result<String, Box<dyn std::error::Error>> {
+        let (frontmatter, _) = self.parse_frontmatter(lexi_content)?;
+        self.project_target(project, cli_target.or(frontmatter.target.as_deref()))
+    }
+
     fn save_profile_config(&self, profile_config: &ProfileConfig) -> Result<(), Box<dyn std::error::Error>> {
         if let Some(parent) = self.config_path.parent() {
             fs::create_dir_all(parent)?;
@@ -222,7 +1305,7 @@ impl LexiCompiler {
         Ok(())
     }
 
-    async fn compile(&self, input: &str, target: &str, output: Option<&str>, run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    async fn compile(&self, input: &str, target: Option<&str>, output: Option<&str>, run: Option<RunOptions>, options: &CompileOptions) -> Result<(), Box<dyn std::error::Error>> {
         // Validate input file
         if !input.ends_with(".lxi") && !input.ends_with(".lexi") {
             eprintln!("❌ Error: Input file must have .lxi or .lexi extension");
@@ -243,668 +1326,5080 @@ impl LexiCompiler {
             process::exit(1);
         }
 
-        // Generate output filename - create owned String
-        let default_output = self.get_default_output_file(input, target);
+        // Explicit flags win over the file's frontmatter, which wins over project defaults
+        let project = &self.project;
+        let target = &match self.file_target(target, &lexi_content, project) {
+            Ok(target) => target,
+            Err(error) => {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        };
+
+        // Generate output filename - create owned String; web pages go to the build directory
+        let mut default_output = self.get_default_output_file(input, target);
+        if self.layout(target) == OutputLayout::WebBundle {
+            default_output = std::path::Path::new(&project.build_dir).join(&default_output).to_string_lossy().to_string();
+        }
         let output_file = output.unwrap_or(&default_output);
+        let output_dir = std::path::Path::new(output_file).parent().unwrap_or(std::path::Path::new(""));
+
+        // Compile imported .lxi files first so this one can call into their output
+        let dependencies = match self.resolve_imports(std::path::Path::new(input)) {
+            Ok(dependencies) => dependencies,
+            Err(error) => {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        };
+        for dependency in &dependencies {
+            self.compile_dependency(dependency, target, output_dir, options).await?;
+        }
 
         println!("🤖 Generating code with AI...");
-        
-        // Generate code using LLM
-        let generated_code = self.generate_code_with_llm(&lexi_content, target).await?;
+        let job = self.prepare_job(input, &lexi_content, target, output_dir, false, options)?;
+        let (mut generated_code, checks_failed) = self.generate_checked(&job, options).await?;
+
+        // Replace block markers with source references and record where each block landed
+        let mut mappings = Vec::new();
+        // Lines of the generated code only map onto a single output file
+        let unmappable = match self.layout(target) {
+            OutputLayout::Notebook | OutputLayout::Patterns(_) => Some("each block already has its own part of the output"),
+            OutputLayout::WebBundle => Some("the output is split into several files"),
+            OutputLayout::File => None,
+        };
+        if let (true, Some(reason)) = (options.source_map || options.inline_refs, unmappable) {
+            println!("⚠️  Source maps not supported for {}; {}", target, reason);
+        } else if options.source_map || options.inline_refs {
+            let source_name = std::path::Path::new(input)
+                .file_name()
+                .and_then(|name| name.to_str())
+                .unwrap_or(input);
+            let blocks = self.parse_blocks(&job.lexi_content);
+            (generated_code, mappings) = self.resolve_block_markers(&generated_code, &blocks, source_name, target, options.inline_refs);
+            if mappings.is_empty() {
+                println!("⚠️  The generated code has no block markers; nothing to trace");
+            }
+        }
+
+        let dangerous = self.find_dangerous(&generated_code, target)?;
+        if !dangerous.is_empty() && !options.allow_dangerous {
+            eprintln!("❌ Refusing to write {}: the generated code contains destructive commands", output_file);
+            for found in &dangerous {
+                eprintln!("   ⚠️  {}", found);
+            }
+            eprintln!("   Review the description, or pass --allow-dangerous to write it anyway");
+            process::exit(1);
+        }
 
-        // Write output file
-        fs::write(output_file, generated_code)?;
+        // Write output file, keeping hand edits made since the last compile
+        let conflicts = self.write_generated(std::path::Path::new(output_file), &generated_code, &job, options.overwrite)?;
         println!("✅ Successfully compiled to {}", output_file);
 
+        if options.source_map && unmappable.is_none() {
+            let map_file = format!("{}.map.json", output_file);
+            // The map locates blocks in the generated code; merged hand edits and restored keep regions move them
+            let written = fs::read_to_string(output_file)?;
+            if written.trim_end_matches('\n') != generated_code.trim_end_matches('\n') {
+                let _ = fs::remove_file(&map_file);
+                println!("⚠️  No source map: your edits to {} moved the generated lines. Recompile with --overwrite to map them", output_file);
+            } else {
+                let source_map = SourceMap {
+                    version: 1,
+                    source: input.to_string(),
+                    output: output_file.to_string(),
+                    mappings,
+                };
+                fs::write(&map_file, serde_json::to_string_pretty(&source_map)?)?;
+                println!("🗺️  Source map written to {}", map_file);
+            }
+        }
+
+        if conflicts > 0 {
+            eprintln!("⚠️  {} conflict(s) merging your edits into {}; resolve the <<<<<<< markers and recompile", conflicts, output_file);
+            process::exit(1);
+        }
+
+        if checks_failed {
+            eprintln!("❌ Generated code still fails its checks after {} attempts", MAX_GENERATION_ATTEMPTS);
+            process::exit(1);
+        }
+
         // Run if requested
-        if run {
+        if let Some(run_options) = run {
             println!("🚀 Running {}...", output_file);
-            self.run_output(output_file, target)?;
+            let exit_code = self.run_output(output_file, target, &run_options)?;
+            if exit_code != 0 {
+                process::exit(exit_code);
+            }
         }
 
         Ok(())
     }
 
-    fn get_default_output_file(&self, input: &str, target: &str) -> String {
-        let base_name = std::path::Path::new(input)
-            .file_stem()
-            .unwrap()
-            .to_str()
-            .unwrap();
-        
-        let extension = match target {
-            "javascript" => ".js",
-            "python" => ".py", 
-            "java" => ".java",
-            "cpp" => ".cpp",
-            "rust" => ".rs",
-            "go" => ".go",
-            "sql" => ".sql",
-            "mongodb" => ".js",
-            "redis" => ".txt",
-            _ => ".js",
-        };
-
-        format!("{}{}", base_name, extension)
-    }
-
-    async fn generate_code_with_llm(&self, lexi_content: &str, target: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let config = self.load_config();
+    async fn compile_dependency(&self, dependency: &std::path::Path, target: &str, output_dir: &std::path::Path, options: &CompileOptions) -> Result<(), Box<dyn std::error::Error>> {
+        let current_dir = std::env::current_dir()?;
+        let input = dependency.strip_prefix(&current_dir).unwrap_or(dependency).to_string_lossy();
+        let output_file = output_dir.join(self.get_default_output_file(&input, target));
 
-        if config.api_key.is_empty() && config.provider != "local" {
-            eprintln!("❌ No API key configured. Run: lexi config set api_key <your-key>");
-            process::exit(1);
+        // Skip dependencies whose output is newer than their source and everything it imports,
+        // directly or not, since an import's generated code is part of the prompt
+        let modified = |path: &std::path::Path| fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let mut inputs = vec![dependency.to_path_buf()];
+        for import in self.resolve_imports(dependency)? {
+            inputs.push(output_dir.join(self.get_default_output_file(&import.to_string_lossy(), target)));
+            inputs.push(import);
+        }
+        let newest_input = inputs.iter().map(|path| modified(path)).max().flatten();
+        if let (Some(source_time), Some(output_time)) = (newest_input, modified(&output_file)) {
+            if output_time >= source_time {
+                return Ok(());
+            }
         }
 
-        let (system_prompt, user_prompt) = self.build_prompt(lexi_content, target);
-
-        let response = match config.provider.as_str() {
-            "openai" => self.call_openai(&system_prompt, &user_prompt, &config).await?,
-            "anthropic" => self.call_anthropic(&system_prompt, &user_prompt, &config).await?,
-            "local" => self.call_ollama(&system_prompt, &user_prompt, &config).await?,
-            "azure" => self.call_azure(&system_prompt, &user_prompt, &config).await?,
-            _ => return Err(format!("Unsupported provider: {}", config.provider).into()),
+        println!("📦 Compiling imported {}...", input);
+        let lexi_content = fs::read_to_string(dependency)?;
+        let job = self.prepare_job(&input, &lexi_content, target, output_dir, true, options)?;
+        let dependency_options = CompileOptions {
+            check_examples: options.check_examples,
+            overwrite: options.overwrite,
+            source_map: false,
+            inline_refs: false,
+            overrides: options.overrides.clone(),
+            schema: options.schema.clone(),
+            allow_dangerous: options.allow_dangerous,
         };
+        let (generated_code, checks_failed) = self.generate_checked(&job, &dependency_options).await?;
+        let dangerous = self.find_dangerous(&generated_code, target)?;
+        if !dangerous.is_empty() && !options.allow_dangerous {
+            return Err(format!("Imported {} contains destructive commands ({}); pass --allow-dangerous to write it", input, dangerous.join("; ")).into());
+        }
+        let conflicts = self.write_generated(&output_file, &generated_code, &job, options.overwrite)?;
 
-        Ok(self.extract_code_from_response(&response, target))
+        if conflicts > 0 || checks_failed {
+            return Err(format!("Imported {} did not compile cleanly; fix it before compiling its importers", input).into());
+        }
+        println!("✅ Compiled {} to {}", input, output_file.display());
+        Ok(())
     }
 
-    fn build_prompt(&self, lexi_content: &str, target: &str) -> (String, String) {
-        let system_prompt = match target {
-            "sql" => format!(
-                "You are Lexi, a database query generator that converts English descriptions into clean, efficient SQL.
-
-Rules:
-1. Generate only the SQL code, no explanations or markdown
-2. Use proper SQL syntax with appropriate JOINs, WHERE clauses, and indexing considerations
-3. Include comments for complex queries
-4. Use standard SQL that works across major databases (PostgreSQL, MySQL, SQL Server)
-5. Generate complete, working SQL statements
-6. Consider performance and use appropriate LIMIT clauses when needed
-
-Target: SQL"
-            ),
-            "mongodb" => format!(
-                "You are Lexi, a MongoDB query generator that converts English descriptions into MongoDB queries.
-
-Rules:
-1. Generate only MongoDB JavaScript code, no explanations or markdown
-2. Use proper MongoDB syntax with appropriate aggregation pipelines
-3. Include comments for complex operations
-4. Use modern MongoDB methods and operators
-5. Generate complete, working MongoDB queries
-6. Consider performance with appropriate indexing hints
-
-Target: MongoDB JavaScript"
-            ),
-            "redis" => format!(
-                "You are Lexi, a Redis command generator that converts English descriptions into Redis commands.
-
-Rules:
-1. Generate only Redis commands, no explanations or markdown
-2. Use proper Redis syntax and data structures
-3. Include comments for complex operations
-4. Use appropriate Redis commands for the use case
-5. Generate complete, working Redis command sequences
-6. Consider memory usage and TTL when appropriate
-
-Target: Redis"
-            ),
-            _ => format!(
-                "You are Lexi, a code generator that converts English descriptions into clean, functional {} code.
+    fn prepare_job<'a>(
+        &self,
+        input: &'a str,
+        lexi_content: &'a str,
+        target: &'a str,
+        output_dir: &'a std::path::Path,
+        library: bool,
+        options: &CompileOptions,
+    ) -> Result<CompileJob<'a>, Box<dyn std::error::Error>> {
+        let (frontmatter, body) = self.parse_frontmatter(lexi_content)?;
+        let lexi_content = body.as_str();
+        let mut sections = Vec::new();
 
-Rules:
-1. Generate only the code, no explanations or markdown
-2. Include proper error handling and edge cases
-3. Use modern best practices for {}
-4. Add structural comments but no console.log statements
-5. Make functions keyboard accessible if UI-related
-6. Generate complete, working implementations
+        let imports = self.parse_imports(lexi_content);
+        let spec = self.target(target)?;
+        let wiring = spec.module_wiring.clone().unwrap_or_default();
+        if (!imports.is_empty() || library) && spec.module_wiring.is_none() {
+            return Err(format!("@import is not supported for target '{}'", target).into());
+        }
 
-Target language: {}",
-                target, target, target
-            )
-        };
+        let source_dir = std::path::Path::new(input).parent().unwrap_or(std::path::Path::new(""));
+        for import in imports {
+            let dependency = source_dir.join(&import);
+            let module_file = self.get_default_output_file(&dependency.to_string_lossy(), target);
+            let module_name = module_file.split('.').next().unwrap_or_default().to_string();
+            let module_code = fs::read_to_string(output_dir.join(&module_file))
+                .map_err(|_| format!("Output of imported {} not found in {}", import, output_dir.display()))?;
+            let import_rule = wiring.import.replace("{module}", &module_name).replace("{file}", &module_file);
 
-        let user_prompt = match target {
-            "sql" => format!(
-                "Convert this description into SQL:
+            sections.push(format!(
+                "This program imports module `{}`, already generated as {}:
 
 {}
 
-Generate clean, efficient SQL with proper syntax.",
-                lexi_content
-            ),
-            "mongodb" => format!(
-                "Convert this description into MongoDB JavaScript:
+{}. Call its functions and types by their declared names; do not reimplement them.",
+                module_name, module_file, module_code, import_rule
+            ));
+        }
 
-{}
+        if library {
+            sections.push(format!("This file is a library module imported by other files. {}.", wiring.export));
+        }
 
-Generate clean MongoDB queries with proper syntax.",
-                lexi_content
-            ),
-            "redis" => format!(
-                "Convert this description into Redis commands:
+        if matches!(spec.layout, OutputLayout::Patterns(_)) && self.parse_blocks(lexi_content).iter().all(|block| block.regex_cases.is_empty()) {
+            return Err("No paragraph has `Should match:` or `Should not match:` cases; each pattern is generated for a paragraph with cases".into());
+        }
 
-{}
+        // Declared types are rendered by Lexi, not the model, so every compile agrees on them
+        let types = self.parse_types(lexi_content)?;
+        // These targets have no hashable or ordered type that holds any value
+        if matches!(spec.type_syntax, Some(TypeSyntax::Rust | TypeSyntax::Cpp | TypeSyntax::TypeScript)) {
+            let mut fields = types.iter().flat_map(|declared| &declared.fields);
+            if let Some(field) = fields.find(|field| field.lexi_type.replace(' ', "").contains("map<any,")) {
+                return Err(format!("Field '{}': map keys of type any are not supported for target '{}'", field.name, target).into());
+            }
+        }
+        let type_definitions = if types.is_empty() {
+            None
+        } else {
+            let definitions = self
+                .render_types(target, &types)
+                .ok_or_else(|| format!("Type declarations are not supported for target '{}'", target))?;
+            sections.push(format!(
+                "These types are already defined at the top of the file. Use them as-is and do not redefine them:\n\n{}",
+                definitions
+            ));
+            Some(definitions)
+        };
 
-Generate clean Redis commands with proper syntax.",
-                lexi_content
-            ),
-            _ => format!(
-                "Convert this Lexi description into {} code:
+        let examples = self.load_few_shot_examples(target)?;
+        let blocks = self.parse_blocks(lexi_content);
+        let selected = self.select_few_shot_examples(&examples, &blocks);
+        if !selected.is_empty() {
+            let mut section = String::from("Approved examples from this project. Match their idioms, naming and structure:");
+            for example in selected {
+                section.push_str(&format!("\n\nDescription:\n{}\n\nCode:\n{}", example.description, example.code));
+            }
+            sections.push(section);
+        }
 
-{}
+        let data_model = self.load_data_model(target)?;
+        if let Some(model) = &data_model {
+            sections.push(self.describe_data_model(model));
+        }
 
-Generate clean, production-ready code with proper function names and structure.",
-                target, lexi_content
-            )
+        let (style, style_rules) = self.load_style_guide(target, frontmatter.style.as_deref())?;
+
+        // A schema from the command line is relative to the working directory, one from frontmatter to the file
+        let schema_path = match (&options.schema, &frontmatter.schema) {
+            (Some(path), _) => Some(PathBuf::from(path)),
+            (None, Some(path)) => Some(source_dir.join(path)),
+            (None, None) => None,
+        };
+        let schema = match schema_path {
+            Some(path) => {
+                let ddl = fs::read_to_string(&path).map_err(|error| format!("Failed to read schema {}: {}", path.display(), error))?;
+                sections.push(format!(
+                    "The database has this schema. Use only its tables and columns:\n\n{}",
+                    ddl.trim()
+                ));
+                Some(ddl)
+            }
+            None => None,
         };
 
-        (system_prompt, user_prompt)
+        Ok(CompileJob {
+            input,
+            lexi_content: body.clone(),
+            target,
+            overrides: options.overrides.or(&frontmatter.overrides),
+            schema,
+            data_model,
+            style,
+            style_rules,
+            output_dir,
+            context: sections.join("\n\n"),
+            type_definitions,
+        })
     }
 
-    async fn call_openai(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-        let client = reqwest::Client::new();
-        
-        let request = OpenAIRequest {
-            model: config.model.clone(),
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: user_prompt.to_string(),
-                },
-            ],
-            temperature: config.temperature,
-            max_tokens: config.max_tokens,
-        };
-
-        let response = client
-            .post("https://api.openai.com/v1/chat/completions")
-            .header("Authorization", format!("Bearer {}", config.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    fn prompt_content(&self, job: &CompileJob<'_>, blocks: &[LexiBlock], options: &CompileOptions) -> String {
+        let mut prompt_content = job.lexi_content.clone();
+        if !job.context.is_empty() {
+            prompt_content = format!("{}\n\n{}", prompt_content, job.context);
+        }
+        // Ask for block markers so the output can be traced back to its source lines, or split into notebook cells
+        if options.source_map || options.inline_refs || self.layout(job.target) == OutputLayout::Notebook {
+            prompt_content = format!("{}\n\n{}", prompt_content, self.build_trace_instructions(blocks, job.target));
+        }
+        prompt_content
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("OpenAI API error: {}", error_text).into());
+    fn load_few_shot_examples(&self, target: &str) -> Result<Vec<FewShotExample>, Box<dyn std::error::Error>> {
+        // Dialects like sql:sqlite use sql-sqlite, since `:` is not allowed in Windows file names
+        let dir = PathBuf::from(&self.project.examples_dir).join(target.replace(':', "-"));
+        if !dir.is_dir() {
+            return Ok(Vec::new());
         }
 
-        let openai_response: OpenAIResponse = response.json().await?;
-        Ok(openai_response.choices[0].message.content.clone())
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)?.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        paths.sort();
+
+        // Each description.lxi is paired with the file sharing its name, e.g. parse_csv.lxi and parse_csv.py
+        let mut examples = Vec::new();
+        for path in paths.iter().filter(|path| path.extension().is_some_and(|extension| extension == "lxi")) {
+            let Some(code_path) = paths
+                .iter()
+                .find(|other| *other != path && other.file_stem() == path.file_stem())
+            else {
+                continue;
+            };
+            let (_, description) = self.parse_frontmatter(&fs::read_to_string(path)?)?;
+            examples.push(FewShotExample {
+                description: description.trim().to_string(),
+                code: fs::read_to_string(code_path)?.trim_end().to_string(),
+            });
+        }
+        Ok(examples)
     }
 
-    async fn call_anthropic(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-        let client = reqwest::Client::new();
-        
-        let request = AnthropicRequest {
-            model: config.model.clone(),
-            max_tokens: config.max_tokens,
-            messages: vec![Message {
-                role: "user".to_string(),
-                content: format!("{}\n\n{}", system_prompt, user_prompt),
-            }],
-        };
+    // Picks the examples most similar to any block, best first
+    fn select_few_shot_examples<'e>(&self, examples: &'e [FewShotExample], blocks: &[LexiBlock]) -> Vec<&'e FewShotExample> {
+        let block_words: Vec<HashMap<String, f64>> = blocks.iter().map(|block| self.word_counts(&block.text)).collect();
+        let mut scored: Vec<(f64, &FewShotExample)> = examples
+            .iter()
+            .map(|example| {
+                let words = self.word_counts(&example.description);
+                let score = block_words
+                    .iter()
+                    .map(|block| self.cosine_similarity(block, &words))
+                    .fold(0.0, f64::max);
+                (score, example)
+            })
+            .filter(|(score, _)| *score >= MIN_FEW_SHOT_SIMILARITY)
+            .collect();
 
-        let response = client
-            .post("https://api.anthropic.com/v1/messages")
-            .header("x-api-key", &config.api_key)
-            .header("Content-Type", "application/json")
-            .header("anthropic-version", "2023-06-01")
-            .json(&request)
-            .send()
-            .await?;
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.into_iter().take(MAX_FEW_SHOT_EXAMPLES).map(|(_, example)| example).collect()
+    }
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("Anthropic API error: {}", error_text).into());
-        }
+    fn word_counts(&self, text: &str) -> HashMap<String, f64> {
+        const IGNORED: [&str; 12] = ["the", "and", "that", "for", "with", "from", "into", "this", "create", "function", "returns", "return"];
 
-        let anthropic_response: AnthropicResponse = response.json().await?;
-        Ok(anthropic_response.content[0].text.clone())
+        let mut counts = HashMap::new();
+        for word in text.split(|c: char| !c.is_alphanumeric()).map(str::to_lowercase) {
+            if word.len() > 2 && !IGNORED.contains(&word.as_str()) {
+                *counts.entry(word).or_insert(0.0) += 1.0;
+            }
+        }
+        counts
     }
 
-    async fn call_ollama(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-        let client = reqwest::Client::new();
-        let base_url = if config.base_url.is_empty() {
-            "http://localhost:11434"
+    fn cosine_similarity(&self, a: &HashMap<String, f64>, b: &HashMap<String, f64>) -> f64 {
+        let dot: f64 = a.iter().filter_map(|(word, count)| b.get(word).map(|other| count * other)).sum();
+        let norm = |counts: &HashMap<String, f64>| counts.values().map(|count| count * count).sum::<f64>().sqrt();
+        if dot == 0.0 {
+            0.0
         } else {
-            &config.base_url
-        };
+            dot / (norm(a) * norm(b))
+        }
+    }
 
-        let request = OllamaRequest {
-            model: config.model.clone(),
-            prompt: format!("{}\n\n{}", system_prompt, user_prompt),
-            stream: false,
-        };
+    fn load_data_model(&self, target: &str) -> Result<Option<DataModel>, Box<dyn std::error::Error>> {
+        let project = &self.project;
+        match target {
+            "mongodb" if !project.mongodb.collections.is_empty() => {
+                let mut collections = Vec::new();
+                for (name, document) in &project.mongodb.collections {
+                    // A string is the path of a .json file holding the sample or schema
+                    let document = match document {
+                        serde_json::Value::String(path) => {
+                            let text = fs::read_to_string(path).map_err(|error| format!("Failed to read collection {} from {}: {}", name, path, error))?;
+                            serde_json::from_str(&text).map_err(|error| format!("Invalid JSON in {}: {}", path, error))?
+                        }
+                        document => document.clone(),
+                    };
+                    let mut fields = Vec::new();
+                    self.document_fields(&document, "", &mut fields);
+                    collections.push((name.clone(), fields));
+                }
+                collections.sort_by(|a, b| a.0.cmp(&b.0));
+                Ok(Some(DataModel::Mongo(collections)))
+            }
+            "redis" if !project.redis.keys.is_empty() => {
+                let types = ["string", "hash", "list", "set", "zset", "stream"];
+                if let Some(key) = project.redis.keys.iter().find(|key| !types.contains(&key.key_type.as_str())) {
+                    return Err(format!("Redis key {} has unknown type '{}' (expected one of {})", key.pattern, key.key_type, types.join(", ")).into());
+                }
+                Ok(Some(DataModel::Redis(project.redis.keys.clone())))
+            }
+            _ => Ok(None),
+        }
+    }
 
-        let response = client
-            .post(&format!("{}/api/generate", base_url))
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+    // Collects dotted field paths and their types from a sample document or a JSON schema
+    fn document_fields(&self, document: &serde_json::Value, prefix: &str, fields: &mut Vec<(String, String)>) {
+        let document = document.get("$jsonSchema").unwrap_or(document);
+        let path = |key: &str| if prefix.is_empty() { key.to_string() } else { format!("{}.{}", prefix, key) };
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("Ollama API error: {}", error_text).into());
+        if let Some(properties) = document.get("properties").and_then(|properties| properties.as_object()) {
+            for (key, property) in properties {
+                let field_type = match property.get("bsonType").or_else(|| property.get("type")) {
+                    Some(serde_json::Value::String(name)) => name.clone(),
+                    Some(serde_json::Value::Array(names)) => names.iter().filter_map(|name| name.as_str()).collect::<Vec<_>>().join("|"),
+                    _ => "any".to_string(),
+                };
+                fields.push((path(key), field_type));
+                // Nested objects and arrays of objects are schemas of their own
+                for nested in [Some(property), property.get("items")].into_iter().flatten() {
+                    if nested.get("properties").is_some() {
+                        self.document_fields(nested, &path(key), fields);
+                    }
+                }
+            }
+            return;
         }
 
-        let ollama_response: OllamaResponse = response.json().await?;
-        Ok(ollama_response.response)
+        let Some(object) = document.as_object() else {
+            return;
+        };
+        for (key, value) in object {
+            let field_type = match value {
+                serde_json::Value::String(_) => "string".to_string(),
+                serde_json::Value::Number(_) => "number".to_string(),
+                serde_json::Value::Bool(_) => "bool".to_string(),
+                serde_json::Value::Null => "null".to_string(),
+                serde_json::Value::Array(_) => "array".to_string(),
+                // Extended JSON such as {"$oid": ...} or {"$date": ...} is a single value
+                serde_json::Value::Object(inner) if inner.keys().all(|key| key.starts_with('$')) => {
+                    inner.keys().next().map(|key| key.trim_start_matches('$').to_string()).unwrap_or_default()
+                }
+                serde_json::Value::Object(_) => "object".to_string(),
+            };
+            let is_leaf = !field_type.eq("object") && !field_type.eq("array");
+            fields.push((path(key), field_type));
+            if is_leaf {
+                continue;
+            }
+            match value {
+                serde_json::Value::Array(items) => {
+                    if let Some(first) = items.first() {
+                        self.document_fields(first, &path(key), fields);
+                    }
+                }
+                _ => self.document_fields(value, &path(key), fields),
+            }
+        }
     }
 
-    async fn call_azure(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
-        if config.base_url.is_empty() {
-            return Err("Azure baseUrl not configured. Set: lexi config set base_url https://your-resource.openai.azure.com".into());
+    fn describe_data_model(&self, model: &DataModel) -> String {
+        match model {
+            DataModel::Mongo(collections) => {
+                let mut description = String::from("The database has these collections. Use only these collections and fields:");
+                for (name, fields) in collections {
+                    let fields: Vec<String> = fields.iter().map(|(field, field_type)| format!("{} ({})", field, field_type)).collect();
+                    description.push_str(&format!("\n- {}: {}", name, fields.join(", ")));
+                }
+                description
+            }
+            DataModel::Redis(keys) => {
+                let mut description = String::from("Use only these keys. Parts in braces stand for any value:");
+                for key in keys {
+                    description.push_str(&format!("\n- {} ({}", key.pattern, key.key_type));
+                    if !key.fields.is_empty() {
+                        description.push_str(&format!(" with fields {}", key.fields.join(", ")));
+                    }
+                    description.push(')');
+                    if let Some(text) = &key.description {
+                        description.push_str(&format!(": {}", text));
+                    }
+                }
+                description
+            }
         }
+    }
 
-        let client = reqwest::Client::new();
-        let url = format!(
-            "{}/openai/deployments/{}/chat/completions?api-version=2023-12-01-preview",
-            config.base_url, config.model
-        );
+    fn check_data_model(&self, code: &str, model: &DataModel) -> Vec<String> {
+        match model {
+            DataModel::Mongo(collections) => self.check_mongo(code, collections),
+            DataModel::Redis(keys) => self.check_redis(code, keys),
+        }
+    }
 
-        let request = OpenAIRequest {
-            model: config.model.clone(),
-            messages: vec![
-                Message {
-                    role: "system".to_string(),
-                    content: system_prompt.to_string(),
-                },
-                Message {
-                    role: "user".to_string(),
-                    content: user_prompt.to_string(),
-                },
-            ],
-            temperature: config.temperature,
-            max_tokens: config.max_tokens,
+    fn check_mongo(&self, code: &str, collections: &[(String, Vec<(String, String)>)]) -> Vec<String> {
+        let mut violations = Vec::new();
+        let mut report = |violation: String| {
+            if !violations.contains(&violation) {
+                violations.push(violation);
+            }
         };
+        let all_fields: Vec<(String, String)> = collections.iter().flat_map(|(_, fields)| fields.clone()).collect();
 
-        let response = client
-            .post(&url)
-            .header("api-key", &config.api_key)
-            .header("Content-Type", "application/json")
-            .json(&request)
-            .send()
-            .await?;
+        static COLLECTION_REF: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+            regex::Regex::new(r#"\bdb\s*\.\s*(?:getCollection\(\s*["'`]([^"'`]+)["'`]\s*\)|([A-Za-z_]\w*)\s*\.)"#).expect("valid collection pattern")
+        });
+        static CALL: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+            regex::Regex::new(
+                r"\.\s*(find|findOne|countDocuments|deleteOne|deleteMany|findOneAndDelete|updateOne|updateMany|findOneAndUpdate|replaceOne|findOneAndReplace|insertOne|insertMany|distinct|aggregate|sort)\s*\(",
+            )
+            .expect("valid call pattern")
+        });
+        static KEY: std::sync::LazyLock<regex::Regex> =
+            std::sync::LazyLock::new(|| regex::Regex::new(r#"(?:^|[{,\s])["'`]?([A-Za-z_]\w*)["'`]?\s*:"#).expect("valid key pattern"));
+        static FIELD_PATH: std::sync::LazyLock<regex::Regex> =
+            std::sync::LazyLock::new(|| regex::Regex::new(r#"["'`]\$([A-Za-z_][\w.]*)["'`]"#).expect("valid field path pattern"));
 
-        if !response.status().is_success() {
-            let error_text = response.text().await?;
-            return Err(format!("Azure API error: {}", error_text).into());
+        let mut references = Vec::new();
+        for captures in COLLECTION_REF.captures_iter(code) {
+            let name = captures.get(1).or_else(|| captures.get(2)).map(|name| name.as_str()).unwrap_or_default();
+            let position = captures.get(0).map(|found| found.start()).unwrap_or_default();
+            if !collections.iter().any(|(declared, _)| declared == name) {
+                report(format!("collection `{}` is not declared", name));
+            }
+            references.push((position, name));
         }
 
-        let azure_response: OpenAIResponse = response.json().await?;
-        Ok(azure_response.choices[0].message.content.clone())
-    }
-
-    fn extract_code_from_response(&self, response: &str, _target: &str) -> String {
-        // Remove markdown code blocks if present
-        let mut code = response
-            .replace("```javascript", "")
-            .replace("```python", "")
-            .replace("```java", "")
-            .replace("```cpp", "")
-            .replace("```rust", "")
-            .replace("```go", "")
-            .replace("```", "");
+        for captures in CALL.captures_iter(code) {
+            let (Some(method), Some(found)) = (captures.get(1), captures.get(0)) else {
+                continue;
+            };
+            // Fields belong to the collection named closest before the call; skip undeclared ones
+            let collection = references.iter().rev().find(|(position, _)| *position < found.start()).map(|(_, name)| *name);
+            let (name, fields) = match collection {
+                Some(name) => match collections.iter().find(|(declared, _)| declared == name) {
+                    Some((_, fields)) => (name, fields),
+                    None => continue,
+                },
+                None => ("query", &all_fields),
+            };
 
-        // Remove leading/trailing whitespace
-        code = code.trim().to_string();
+            let open = found.end() - 1;
+            let Some(close) = self.js_bracket_end(&code[open..]) else {
+                continue;
+            };
+            let args = self.split_js_top_level(&code[open + 1..open + close]);
+            let arg = |index: usize| args.get(index).map(|arg| arg.trim()).unwrap_or_default();
+            // Field names used as keys of `object`, looking inside $and/$or/$nor when it is a filter
+            let keys = |object: &str, filter: bool| {
+                let mut keys = Vec::new();
+                for (key, value) in self.js_object_entries(object) {
+                    match key.strip_prefix('$') {
+                        Some(operator) if filter && ["and", "or", "nor"].contains(&operator) => {
+                            for condition in self.split_js_top_level(value.trim().trim_start_matches('[').trim_end_matches(']')) {
+                                keys.extend(self.js_object_entries(condition.trim()).into_iter().map(|(nested, _)| nested));
+                            }
+                        }
+                        Some(_) => {}
+                        None => keys.push(key),
+                    }
+                }
+                keys
+            };
 
-        // Find first line that looks like code
-        let lines: Vec<&str> = code.lines().collect();
-        let mut start_index = 0;
-        let mut end_index = lines.len();
+            let mut used = Vec::new();
 
-        for (i, line) in lines.iter().enumerate() {
-            let trimmed = line.trim();
-            if trimmed.starts_with("function ")
-                || trimmed.starts_with("def ")
-                || trimmed.starts_with("class ")
-                || trimmed.starts_with("import ")
-                || trimmed.starts_with("const ")
-                || trimmed.starts_with("let ")
-                || trimmed.starts_with("var ")
-                || trimmed.starts_with("#include")
-            {
-                start_index = i;
-                break;
+            match method.as_str() {
+                "find" | "findOne" | "replaceOne" | "findOneAndReplace" => {
+                    used.extend(keys(arg(0), true));
+                    used.extend(keys(arg(1), false));
+                }
+                "updateOne" | "updateMany" | "findOneAndUpdate" => {
+                    used.extend(keys(arg(0), true));
+                    // Update operators such as $set name the fields they change
+                    for (_, value) in self.js_object_entries(arg(1)) {
+                        used.extend(keys(value.trim(), false));
+                    }
+                }
+                "insertOne" | "sort" => used.extend(keys(arg(0), false)),
+                "insertMany" => {
+                    for document in self.split_js_top_level(arg(0).trim_start_matches('[').trim_end_matches(']')) {
+                        used.extend(keys(document.trim(), false));
+                    }
+                }
+                "distinct" => {
+                    used.push(arg(0).trim_matches(|c| c == '"' || c == '\'' || c == '`').to_string());
+                    used.extend(keys(arg(1), true));
+                }
+                "aggregate" => {
+                    for stage in self.split_js_top_level(arg(0).trim_start_matches('[').trim_end_matches(']')) {
+                        for (operator, value) in self.js_object_entries(stage.trim()) {
+                            if operator == "$match" {
+                                used.extend(keys(value.trim(), true));
+                            }
+                        }
+                    }
+                }
+                _ => used.extend(keys(arg(0), true)),
+            }
+            for field in used {
+                if !self.field_declared(&field, fields) {
+                    report(format!("{}: field `{}` is not declared", name, field));
+                }
             }
         }
 
-        // Find last line that looks like code
-        for (i, line) in lines.iter().enumerate().rev() {
-            let trimmed = line.trim();
-            if !trimmed.is_empty() && !trimmed.starts_with("Note:") && !trimmed.starts_with("Example:") {
-                end_index = i + 1;
-                break;
+        // Field paths in expressions ("$total") may also name fields the pipeline itself creates
+        let defined: Vec<&str> = KEY.captures_iter(code).filter_map(|captures| captures.get(1)).map(|key| key.as_str()).collect();
+        for captures in FIELD_PATH.captures_iter(code) {
+            let path = captures.get(1).map(|path| path.as_str()).unwrap_or_default();
+            let root = path.split('.').next().unwrap_or_default();
+            if !self.field_declared(path, &all_fields) && !defined.contains(&root) {
+                report(format!("field path `${}` is not declared", path));
             }
         }
 
-        lines[start_index..end_index].join("\n")
+        violations
     }
 
-    fn init_project(&self, project_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let project_path = std::path::Path::new(project_name);
-        
-        if project_path.exists() {
-            eprintln!("❌ Error: Directory '{}' already exists", project_name);
-            process::exit(1);
-        }
-
-        // Create project structure
-        fs::create_dir_all(project_path.join("src"))?;
-        fs::create_dir_all(project_path.join("build"))?;
-
-        // Create sample .lxi file
-        let sample_lexi = r#"# Sample Lexi Program
-# Write your logic in plain English below
-
-Create a function that takes a list of numbers and returns only the even ones greater than 10
+    fn field_declared(&self, path: &str, fields: &[(String, String)]) -> bool {
+        // Array positions like `items.0` or `items.$` don't name fields
+        let path = path
+            .split('.')
+            .filter(|segment| !segment.starts_with('$') && !segment.chars().all(|c| c.is_ascii_digit()))
+            .collect::<Vec<_>>()
+            .join(".");
+        let opaque = |field: &str| !fields.iter().any(|(other, _)| other.starts_with(&format!("{}.", field)));
 
-Create a function to check if a string is a palindrome, ignoring case and spaces
+        path == "_id"
+            || fields
+                .iter()
+                .any(|(field, _)| *field == path || (path.starts_with(&format!("{}.", field)) && opaque(field)))
+    }
 
-Build a simple web server that responds with "Hello, World!" on GET requests to the root path
-"#;
+    // Calls `visit` with each byte outside strings and comments and the bracket depth before it;
+    // stops at the first byte `visit` returns true for
+    fn js_scan(&self, text: &str, mut visit: impl FnMut(usize, u8, i32) -> bool) -> Option<usize> {
+        let bytes = text.as_bytes();
+        let mut depth = 0;
+        let mut index = 0;
+        while index < bytes.len() {
+            match bytes[index] {
+                quote @ (b'"' | b'\'' | b'`') => {
+                    index += 1;
+                    while index < bytes.len() && bytes[index] != quote {
+                        index += if bytes[index] == b'\\' { 2 } else { 1 };
+                    }
+                }
+                b'/' if bytes.get(index + 1) == Some(&b'/') => {
+                    while index < bytes.len() && bytes[index] != b'\n' {
+                        index += 1;
+                    }
+                }
+                b'/' if bytes.get(index + 1) == Some(&b'*') => {
+                    index += 2;
+                    while index < bytes.len() && !(bytes[index] == b'*' && bytes.get(index + 1) == Some(&b'/')) {
+                        index += 1;
+                    }
+                    index += 1;
+                }
+                byte => {
+                    if visit(index, byte, depth) {
+                        return Some(index);
+                    }
+                    match byte {
+                        b'(' | b'[' | b'{' => depth += 1,
+                        b')' | b']' | b'}' => depth -= 1,
+                        _ => {}
+                    }
+                }
+            }
+            index += 1;
+        }
+        None
+    }
 
-        fs::write(project_path.join("src/main.lxi"), sample_lexi)?;
+    // Index of the bracket closing the one `text` starts with
+    fn js_bracket_end(&self, text: &str) -> Option<usize> {
+        self.js_scan(text, |_, byte, depth| depth == 1 && matches!(byte, b')' | b']' | b'}'))
+    }
 
-        // Create lexi.config.json
-        let config = serde_json::json!({
-            "name": project_name,
-            "version": "1.0.0",
-            "defaultTarget": "javascript",
-            "sourceDir": "src",
-            "buildDir": "build",
-            "targets": {
-                "javascript": { "extension": ".js" },
-                "python": { "extension": ".py" },
-                "java": { "extension": ".java" }
+    fn split_js_top_level<'t>(&self, text: &'t str) -> Vec<&'t str> {
+        let mut commas = Vec::new();
+        self.js_scan(text, |index, byte, depth| {
+            if byte == b',' && depth == 0 {
+                commas.push(index);
             }
+            false
         });
 
-        fs::write(
-            project_path.join("lexi.config.json"),
-            serde_json::to_string_pretty(&config)?,
-        )?;
+        let mut parts = Vec::new();
+        let mut start = 0;
+        for comma in commas {
+            parts.push(&text[start..comma]);
+            start = comma + 1;
+        }
+        if !text[start..].trim().is_empty() {
+            parts.push(&text[start..]);
+        }
+        parts
+    }
 
-        // Create README
-        let readme = format!(
-            r#"# {}
+    // Keys and value text of an object literal; empty when `text` is not one
+    fn js_object_entries<'t>(&self, text: &'t str) -> Vec<(String, &'t str)> {
+        if !text.starts_with('{') {
+            return Vec::new();
+        }
+        let Some(end) = self.js_bracket_end(text) else {
+            return Vec::new();
+        };
 
-A Lexi project - code generated from English descriptions using AI.
+        let mut entries = Vec::new();
+        for entry in self.split_js_top_level(&text[1..end]) {
+            let entry = entry.trim();
+            if entry.is_empty() || entry.starts_with("...") || entry.starts_with('[') {
+                continue;
+            }
+            // Shorthand properties like `{ email }` name the field and the variable
+            let (key, value) = match self.js_scan(entry, |_, byte, depth| depth == 0 && byte == b':') {
+                Some(colon) => (&entry[..colon], &entry[colon + 1..]),
+                None => (entry, entry),
+            };
+            let key = key.trim().trim_matches(|c| c == '"' || c == '\'' || c == '`');
+            entries.push((key.to_string(), value));
+        }
+        entries
+    }
 
-## Getting Started
+    fn check_redis(&self, code: &str, keys: &[RedisKey]) -> Vec<String> {
+        let mut violations = Vec::new();
+        for (index, line) in code.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+            let mut words = self.redis_words(line);
+            if words.first().is_some_and(|word| word == "redis-cli") {
+                words.remove(0);
+            }
+            let Some(command) = words.first().map(|word| word.to_uppercase()) else {
+                continue;
+            };
+            let args = &words[1..];
 
-1. Configure your LLM provider:
-   ```bash
-   lexi config set provider openai
-   lexi config set model gpt-4
-   lexi config set api_key sk-your-key-here
-   ```
+            let key_args: Vec<&String> = match command.as_str() {
+                "DEL" | "EXISTS" | "UNLINK" | "MGET" | "TOUCH" | "WATCH" | "SINTER" | "SUNION" | "SDIFF" => args.iter().collect(),
+                "MSET" | "MSETNX" => args.iter().step_by(2).collect(),
+                "RENAME" | "RENAMENX" | "SMOVE" | "RPOPLPUSH" | "LMOVE" | "COPY" => args.iter().take(2).collect(),
+                "KEYS" => args.iter().take(1).collect(),
+                "SCAN" => args.iter().skip_while(|arg| !arg.eq_ignore_ascii_case("MATCH")).skip(1).take(1).collect(),
+                "MULTI" | "EXEC" | "DISCARD" | "PING" | "ECHO" | "SELECT" | "FLUSHDB" | "FLUSHALL" | "INFO" | "EVAL" | "EVALSHA"
+                | "PUBLISH" | "SUBSCRIBE" | "PSUBSCRIBE" | "CONFIG" | "CLIENT" | "SCRIPT" | "DBSIZE" | "TIME" | "AUTH" => Vec::new(),
+                _ => args.iter().take(1).collect(),
+            };
 
-2. Write your logic in `src/main.lxi`
+            for key in key_args {
+                let Some(declared) = keys.iter().find(|declared| self.redis_key_matches(key, &declared.pattern)) else {
+                    violations.push(format!("line {}: key `{}` does not match any declared key pattern", index + 1, key));
+                    continue;
+                };
+                if let Some(command_type) = self.redis_command_type(&command) {
+                    if command_type != declared.key_type {
+                        violations.push(format!(
+                            "line {}: {} works on a {}, but `{}` is declared as {}",
+                            index + 1,
+                            command,
+                            command_type,
+                            declared.pattern,
+                            declared.key_type
+                        ));
+                        continue;
+                    }
+                }
 
-3. Compile:
-   ```bash
-   lexi compile src/main.lxi --target javascript
-   ```
+                let fields: Vec<&String> = match command.as_str() {
+                    "HGET" | "HEXISTS" | "HSTRLEN" | "HSETNX" | "HINCRBY" | "HINCRBYFLOAT" => args.iter().skip(1).take(1).collect(),
+                    "HDEL" | "HMGET" => args.iter().skip(1).collect(),
+                    "HSET" | "HMSET" => args.iter().skip(1).step_by(2).collect(),
+                    _ => Vec::new(),
+                };
+                for field in fields {
+                    if !declared.fields.is_empty() && !declared.fields.contains(field) {
+                        violations.push(format!("line {}: `{}` has no declared field `{}`", index + 1, declared.pattern, field));
+                    }
+                }
+            }
+        }
+        violations
+    }
 
-4. Run:
-   ```bash
-   node build/main.js
-   ```
+    // Splits a command line into words, keeping quoted arguments together
+    fn redis_words(&self, line: &str) -> Vec<String> {
+        let mut words = Vec::new();
+        let mut current = String::new();
+        let mut quote = None;
+        for c in line.chars() {
+            match (quote, c) {
+                (Some(open), c) if c == open => quote = None,
+                (Some(_), c) => current.push(c),
+                (None, '"' | '\'') => quote = Some(c),
+                (None, c) if c.is_whitespace() => {
+                    if !current.is_empty() {
+                        words.push(std::mem::take(&mut current));
+                    }
+                }
+                (None, c) => current.push(c),
+            }
+        }
+        if !current.is_empty() {
+            words.push(current);
+        }
+        words
+    }
 
-## Commands
+    // Segments in braces match any value; a generated key may use `*`, `{x}`, `<x>` or `$x` only in their place
+    fn redis_key_matches(&self, key: &str, pattern: &str) -> bool {
+        let key_segments: Vec<&str> = key.split(':').collect();
+        let pattern_segments: Vec<&str> = pattern.split(':').collect();
+        key_segments.len() == pattern_segments.len()
+            && key_segments.iter().zip(&pattern_segments).all(|(segment, expected)| {
+                let placeholder = expected.starts_with('{') && expected.ends_with('}');
+                let generic = *segment == "*" || segment.starts_with('{') || segment.starts_with('<') || segment.starts_with('$');
+                (placeholder && !segment.is_empty()) || (!generic && segment == expected)
+            })
+    }
 
-- `lexi compile <file.lxi>` - Compile to JavaScript (default)
-- `lexi compile <file.lxi> --target python` - Compile to Python  
-- `lexi compile <file.lxi> --run` - Compile and run immediately
-- `lexi config list` - Show current configuration
+    // The key type `command` requires, or None for commands that work on any key or are not in the table
+    fn redis_command_type(&self, command: &str) -> Option<&'static str> {
+        REDIS_COMMAND_TYPES
+            .iter()
+            .find(|(_, commands)| commands.contains(&command))
+            .map(|(key_type, _)| *key_type)
+    }
 
-## Project Structure
+    fn load_style_guide(&self, target: &str, file_style: Option<&str>) -> Result<(Option<String>, Vec<StyleRule>), Box<dyn std::error::Error>> {
+        let project = &self.project;
+        let mut parts = Vec::new();
+        let mut rules = Vec::new();
 
-```
-{}/
-├── src/           # Your .lxi source files
-├── build/         # Compiled output
-├── lexi.config.json
-└── README.md
-```
-"#,
-            project_name, project_name
-        );
+        if let Some(guide) = project.style.get(target) {
+            if let Some(text) = &guide.guide {
+                parts.push(text.trim().to_string());
+            }
+            if let Some(file) = &guide.file {
+                let text = fs::read_to_string(file).map_err(|error| format!("Failed to read style guide {}: {}", file, error))?;
+                parts.push(text.trim().to_string());
+            }
+            for rule in &guide.rules {
+                let pattern = match (&rule.forbid, &rule.require) {
+                    (Some(pattern), None) | (None, Some(pattern)) => pattern,
+                    _ => return Err(format!("Style rule '{}' needs exactly one of forbid or require", rule.message).into()),
+                };
+                regex::Regex::new(pattern).map_err(|error| format!("Invalid pattern in style rule '{}': {}", rule.message, error))?;
+                rules.push(rule.clone());
+            }
+            if !rules.is_empty() {
+                let listed: Vec<String> = rules.iter().map(|rule| format!("- {}", rule.message)).collect();
+                parts.push(format!("Rules that are checked after generation:\n{}", listed.join("\n")));
+            }
+        }
+        if let Some(text) = file_style {
+            parts.push(text.to_string());
+        }
 
-        fs::write(project_path.join("README.md"), readme)?;
+        let style = (!parts.is_empty()).then(|| parts.join("\n\n"));
+        Ok((style, rules))
+    }
 
-        println!("📚 Created new Lexi project: {}", project_name);
-        println!("📁 Project structure:");
-        println!("   {}/", project_name);
-        println!("   ├── src/main.lxi");
-        println!("   ├── build/");
-        println!("   ├── lexi.config.json");
-        println!("   └── README.md");
-        println!();
-        println!("Next steps:");
-        println!("   cd {}", project_name);
-        println!("   lexi config init  # Configure your AI provider");
-        println!("   lexi compile src/main.lxi");
+    // Lines matching the target's denylist, as `line N: <matched text>`
+    fn find_dangerous(&self, code: &str, target: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let comment = self.comment_prefix(target);
+        let mut found = Vec::new();
+        for pattern in &self.target(target)?.denylist {
+            let pattern = regex::Regex::new(pattern).map_err(|error| format!("Invalid denylist pattern for {}: {}", target, error))?;
+            for (index, line) in code.lines().enumerate() {
+                if line.trim_start().starts_with(comment.as_str()) {
+                    continue;
+                }
+                if let Some(matched) = pattern.find(line) {
+                    found.push((index + 1, matched.as_str().trim().to_string()));
+                }
+            }
+        }
+        found.sort();
+        Ok(found.into_iter().map(|(line, matched)| format!("line {}: {}", line, matched)).collect())
+    }
 
-        Ok(())
+    fn check_style(&self, code: &str, rules: &[StyleRule]) -> Vec<String> {
+        let mut violations = Vec::new();
+        for rule in rules {
+            // Patterns were validated when the job was prepared
+            if let Some(pattern) = rule.forbid.as_deref().and_then(|pattern| regex::Regex::new(pattern).ok()) {
+                for (index, line) in code.lines().enumerate() {
+                    if let Some(found) = pattern.find(line) {
+                        violations.push(format!("line {}: {} (found `{}`)", index + 1, rule.message, found.as_str()));
+                    }
+                }
+            }
+            if let Some(pattern) = rule.require.as_deref().and_then(|pattern| regex::Regex::new(pattern).ok()) {
+                if !pattern.is_match(code) {
+                    violations.push(rule.message.clone());
+                }
+            }
+        }
+        violations
     }
 
-    fn set_config(&self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut config = self.load_config();
+    async fn generate_checked(&self, job: &CompileJob<'_>, options: &CompileOptions) -> Result<(String, bool), Box<dyn std::error::Error>> {
+        let (input, lexi_content, target) = (job.input, job.lexi_content.as_str(), job.target);
+        let blocks = self.parse_blocks(lexi_content);
+        let prompt_content = self.prompt_content(job, &blocks, options);
 
-        match key {
-            "provider" => config.provider = value.to_string(),
-            "model" => config.model = value.to_string(),
-            "api_key" => config.api_key = value.to_string(),
-            "base_url" => config.base_url = value.to_string(),
-            "temperature" => config.temperature = value.parse().unwrap_or(0.1),
-            "max_tokens" => config.max_tokens = value.parse().unwrap_or(2000),
-            _ => {
-                eprintln!("❌ Unknown config key: {}", key);
-                process::exit(1);
+        // Generate code using LLM
+        let signatures: Vec<&LexiSignature> = blocks.iter().flat_map(|block| &block.signatures).collect();
+        let mut generated_code = self.generate_code_with_llm(&prompt_content, job, &signatures).await?;
+        generated_code = self.attach_type_definitions(&generated_code, target, job.type_definitions.as_deref());
+
+        let example_count: usize = blocks.iter().map(|block| block.examples.len()).sum();
+        let mut check_examples = options.check_examples && example_count > 0;
+        if check_examples && self.target(target)?.example_runner.is_none() {
+            println!("⚠️  Example checks not supported for {} yet", target);
+            check_examples = false;
+        }
+        let validators: Vec<&String> = self
+            .target(target)?
+            .validators
+            .iter()
+            .filter(|validator| {
+                let program = validator.split_whitespace().next().unwrap_or_default();
+                let available = self.command_available(program);
+                if !available {
+                    println!("⚠️  {} not found; skipping its checks for {}", program, target);
+                }
+                available
+            })
+            .collect();
+        let build_check = self.target(target)?.build_check.and_then(|check| match self.build_check_program(check) {
+            Some(program) => Some((check, program)),
+            None => {
+                println!("⚠️  {} not found; skipping its checks for {}", check.program(), target);
+                None
             }
+        });
+        let mut check_signatures = !signatures.is_empty();
+        if check_signatures && self.target(target)?.definition_pattern.is_none() {
+            println!("⚠️  Signature checks not supported for {} yet", target);
+            check_signatures = false;
         }
 
-        self.save_config(&config)?;
-        println!("✅ Set {} = {}", key, value);
+        // Verify signatures, then style rules and the data model, then the compiler, then examples, regenerating
+        // with the failures as feedback
+        let mut checks_failed = false;
+        let mut attempt = 1;
+        loop {
+            let mut feedback = String::new();
 
-        if key == "api_key" {
-            println!("🔐 API key saved securely to ~/.lexi/config.json");
+            let dangerous = if options.allow_dangerous {
+                Vec::new()
+            } else {
+                self.find_dangerous(&generated_code, target)?
+            };
+            let signature_errors = if check_signatures {
+                self.verify_signatures(&generated_code, target, &signatures)
+            } else {
+                Vec::new()
+            };
+            let style_violations = self.check_style(&generated_code, &job.style_rules);
+            let model_violations = match &job.data_model {
+                Some(model) => self.check_data_model(&generated_code, model),
+                None => Vec::new(),
+            };
+            if !dangerous.is_empty() {
+                println!("🛑 Checking for destructive commands...");
+                for found in &dangerous {
+                    println!("   ❌ {}", found);
+                }
+                feedback = self.build_dangerous_feedback(&dangerous);
+            } else if !signature_errors.is_empty() {
+                println!("📐 Checking {} signature(s)...", signatures.len());
+                for error in &signature_errors {
+                    println!("   ❌ {}", error);
+                }
+                feedback = self.build_signature_feedback(&signature_errors);
+            } else if !style_violations.is_empty() {
+                println!("🎨 Checking {} style rule(s)...", job.style_rules.len());
+                for violation in &style_violations {
+                    println!("   ❌ {}", violation);
+                }
+                feedback = self.build_style_feedback(&style_violations);
+            } else if !model_violations.is_empty() {
+                println!("🗂️  Checking against the declared data model...");
+                for violation in &model_violations {
+                    println!("   ❌ {}", violation);
+                }
+                feedback = self.build_data_model_feedback(&model_violations);
+            } else {
+                let mut compile_errors = Vec::new();
+                if let (Some(schema), "sql:sqlite") = (&job.schema, target) {
+                    println!("🔎 Checking against the schema in SQLite...");
+                    compile_errors = self.validate_sqlite(&generated_code, schema)?;
+                }
+                let mut well_formed = true;
+                let structure = self.target(target)?.structure;
+                if let Some(errors) = structure.map(|format| self.check_structure(&generated_code, format)) {
+                    println!("🔎 Checking the {} structure...", target);
+                    well_formed = errors.is_empty();
+                    compile_errors.extend(errors);
+                }
+                // Tools only see configuration that is at least well-formed
+                if let Some((check, program)) = build_check.filter(|_| well_formed) {
+                    let errors = match check {
+                        BuildCheck::Terraform => {
+                            println!("🔎 Checking with terraform validate...");
+                            self.validate_terraform(&generated_code, job.output_dir)?
+                        }
+                        BuildCheck::Dotnet => {
+                            println!("🔎 Checking with dotnet build...");
+                            self.validate_dotnet(&generated_code, job.output_dir)?
+                        }
+                        BuildCheck::Compose => {
+                            println!("🔎 Checking with {} config...", program);
+                            let validator = format!("{} -f {{file}} config --quiet", program);
+                            self.validate_code(&generated_code, target, &validator, job.output_dir)?
+                        }
+                    };
+                    compile_errors.extend(errors.into_iter().map(|error| format!("{}: {}", program, error)));
+                }
+                for validator in validators.iter().filter(|_| well_formed) {
+                    let program = validator.split_whitespace().next().unwrap_or_default();
+                    println!("🔎 Checking with {}...", program);
+                    let errors = self.validate_code(&generated_code, target, validator, job.output_dir)?;
+                    compile_errors.extend(errors.into_iter().map(|error| format!("{}: {}", program, error)));
+                }
+                if !compile_errors.is_empty() {
+                    for error in &compile_errors {
+                        println!("   ❌ {}", error);
+                    }
+                    feedback = self.build_compile_feedback(&compile_errors);
+                } else if matches!(self.layout(target), OutputLayout::Patterns(_)) {
+                    let case_count: usize = blocks.iter().map(|block| block.regex_cases.len()).sum();
+                    println!("🧪 Checking the patterns against {} case(s)...", case_count);
+                    let failures = match self.layout(target) {
+                        OutputLayout::Patterns(flavor) => self.check_regex(&generated_code, &blocks, flavor)?,
+                        _ => Vec::new(),
+                    };
+                    for failure in &failures {
+                        println!("   ❌ {}", failure);
+                    }
+                    if !failures.is_empty() {
+                        feedback = self.build_regex_feedback(&failures);
+                    }
+                } else if check_examples {
+                    println!("🧪 Checking {} example(s)...", example_count);
+                    let failures = self.check_examples(&generated_code, target, &blocks, job.output_dir)?;
+                    self.report_examples(input, &blocks, &failures);
+                    if !failures.is_empty() {
+                        feedback = self.build_example_feedback(&failures);
+                    }
+                }
+            }
+
+            if feedback.is_empty() {
+                break;
+            }
+            if attempt == MAX_GENERATION_ATTEMPTS {
+                checks_failed = true;
+                break;
+            }
+
+            attempt += 1;
+            println!("🔁 Regenerating with the failures as feedback (attempt {}/{})...", attempt, MAX_GENERATION_ATTEMPTS);
+            let retry_content = format!("{}\n\n{}\nPrevious attempt:\n{}", prompt_content, feedback, generated_code);
+            generated_code = self.generate_code_with_llm(&retry_content, job, &signatures).await?;
+            generated_code = self.attach_type_definitions(&generated_code, target, job.type_definitions.as_deref());
         }
 
-        Ok(())
+        Ok((generated_code, checks_failed))
     }
 
-    fn list_config(&self) {
-        let profile_config = self.load_profile_config();
-        let default_config = Config::default();
-        let config = profile_config.profiles
-            .get(&profile_config.active_profile)
-            .unwrap_or(&default_config);
-            
-        println!("🔧 Lexi Configuration (Profile: {}):", profile_config.active_profile);
-        println!("   provider: {}", config.provider);
-        println!("   model: {}", config.model);
-        println!(
-            "   api_key: {}",
-            if config.api_key.is_empty() {
-                "(not set)".to_string()
-            } else {
-                format!("{}...", &config.api_key[..8.min(config.api_key.len())])
+    // Writes generated code, splitting a web bundle into index.html (the output file) and its siblings,
+    // laying out a notebook's cells and rendering patterns in their flavor
+    fn write_generated(&self, output_file: &std::path::Path, generated_code: &str, job: &CompileJob<'_>, overwrite: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        match self.layout(job.target) {
+            OutputLayout::WebBundle => {}
+            OutputLayout::Notebook => {
+                let notebook = self.build_notebook(generated_code, &self.parse_blocks(&job.lexi_content))?;
+                if !overwrite && self.notebook_edited(output_file) {
+                    return Err(format!(
+                        "{} has cells edited since it was generated, and notebooks are not merged. Pass --overwrite to replace it",
+                        output_file.display()
+                    )
+                    .into());
+                }
+                return self.write_output(output_file, &notebook, true);
             }
-        );
-        println!("   base_url: {}", if config.base_url.is_empty() { "(not set)" } else { &config.base_url });
-        println!("   temperature: {}", config.temperature);
-        println!("   max_tokens: {}", config.max_tokens);
+            OutputLayout::Patterns(flavor) => {
+                let patterns = self.render_regex(generated_code, &self.parse_blocks(&job.lexi_content), job.target, flavor);
+                return self.write_output(output_file, &patterns, overwrite);
+            }
+            OutputLayout::File => return self.write_output(output_file, generated_code, overwrite),
+        }
+
+        let dir = output_file.parent().unwrap_or(std::path::Path::new(""));
+        fs::create_dir_all(dir)?;
+        let mut conflicts = 0;
+        for (name, content) in self.web_files(generated_code) {
+            let file = if name == "index.html" { output_file.to_path_buf() } else { dir.join(name) };
+            conflicts += self.write_output(&file, &content, overwrite)?;
+        }
+        Ok(conflicts)
     }
 
-    fn list_profiles(&self) {
-        let profile_config = self.load_profile_config();
-        println!("👥 Available Profiles:");
-        
-        for (name, config) in &profile_config.profiles {
-            let active_marker = if name == &profile_config.active_profile { " (active)" } else { "" };
-            println!("   • {}{} - {} ({})", name, active_marker, config.provider, config.model);
+    // Whether a notebook's cells differ from the last generation; outputs, execution counts and metadata
+    // that Jupyter adds on save don't count
+    fn notebook_edited(&self, output_file: &std::path::Path) -> bool {
+        let cells = |path: &std::path::Path| {
+            let notebook: serde_json::Value = serde_json::from_str(&fs::read_to_string(path).ok()?).ok()?;
+            let cells = notebook["cells"].as_array()?.iter().map(|cell| {
+                let source = match &cell["source"] {
+                    serde_json::Value::Array(lines) => lines.iter().filter_map(|line| line.as_str()).collect::<String>(),
+                    source => source.as_str().unwrap_or_default().to_string(),
+                };
+                (cell["cell_type"].as_str().unwrap_or_default().to_string(), source.trim_end().to_string())
+            });
+            Some(cells.collect::<Vec<_>>())
+        };
+        match cells(output_file) {
+            Some(existing) => cells(&self.generated_base_path(output_file)) != Some(existing),
+            None => output_file.exists(),
         }
     }
 
-    fn switch_profile(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut profile_config = self.load_profile_config();
-        
-        if !profile_config.profiles.contains_key(name) {
-            eprintln!("❌ Profile '{}' does not exist. Create it first with: lexi profile create {}", name, name);
-            process::exit(1);
+    // Lays out Python with `# lexi:block N` markers as a notebook: each block's description as a markdown
+    // cell followed by its code, after a cell for any shared code before the first marker
+    fn build_notebook(&self, code: &str, blocks: &[LexiBlock]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut preamble: Vec<&str> = Vec::new();
+        let mut sections: Vec<(usize, Vec<&str>)> = Vec::new();
+        for line in code.lines() {
+            match self.parse_block_marker(line) {
+                Some(number) if (1..=blocks.len()).contains(&number) => sections.push((number, Vec::new())),
+                _ => match sections.last_mut() {
+                    Some((_, lines)) => lines.push(line),
+                    None => preamble.push(line),
+                },
+            }
         }
-        
-        profile_config.active_profile = name.to_string();
-        self.save_profile_config(&profile_config)?;
-        
-        println!("✅ Switched to profile '{}'", name);
-        Ok(())
+
+        let source = |text: &str| {
+            let text = text.trim_start_matches('\n').trim_end();
+            serde_json::json!(text.split_inclusive('\n').collect::<Vec<_>>())
+        };
+        let markdown_cell = |text: &str| serde_json::json!({ "cell_type": "markdown", "metadata": {}, "source": source(text) });
+        let code_cell = |lines: &[&str]| {
+            serde_json::json!({ "cell_type": "code", "execution_count": null, "metadata": {}, "outputs": [], "source": source(&lines.join("\n")) })
+        };
+        let blank = |lines: &[&str]| lines.iter().all(|line| line.trim().is_empty());
+
+        let mut cells = Vec::new();
+        if sections.is_empty() {
+            // Without markers the code cannot be split, so it follows the whole description
+            let description: Vec<&str> = blocks.iter().map(|block| block.text.as_str()).collect();
+            cells.push(markdown_cell(&description.join("\n\n")));
+            cells.push(code_cell(&preamble));
+        } else {
+            if !blank(&preamble) {
+                cells.push(code_cell(&preamble));
+            }
+            for (index, block) in blocks.iter().enumerate() {
+                cells.push(markdown_cell(&block.text));
+                for (_, lines) in sections.iter().filter(|(number, lines)| *number == index + 1 && !blank(lines)) {
+                    cells.push(code_cell(lines));
+                }
+            }
+        }
+
+        let notebook = serde_json::json!({
+            "cells": cells,
+            "metadata": {
+                "kernelspec": { "display_name": "Python 3", "language": "python", "name": "python3" },
+                "language_info": { "name": "python" }
+            },
+            "nbformat": 4,
+            "nbformat_minor": 4
+        });
+        Ok(serde_json::to_string_pretty(&notebook)?)
     }
 
-    fn create_profile(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut profile_config = self.load_profile_config();
-        
-        if profile_config.profiles.contains_key(name) {
-            println!("⚠️  Profile '{}' already exists", name);
-            return Ok(());
+    // Splits a web bundle, one fenced block per file, into its files
+    fn web_files(&self, bundle: &str) -> Vec<(&'static str, String)> {
+        let mut files: Vec<(&'static str, String)> = Vec::new();
+        let mut current: Option<(Option<&'static str>, Vec<&str>)> = None;
+
+        for line in bundle.lines() {
+            let trimmed = line.trim();
+            if current.is_some() && trimmed == "```" {
+                if let Some((Some(name), lines)) = current.take() {
+                    if !files.iter().any(|(file, _)| *file == name) {
+                        files.push((name, lines.join("\n")));
+                    }
+                }
+            } else if let Some((_, lines)) = current.as_mut() {
+                lines.push(line);
+            } else if let Some(tag) = trimmed.strip_prefix("```") {
+                let name = match tag.trim().to_lowercase().as_str() {
+                    "js" => Some("app.js"),
+                    tag => WEB_FILES.iter().find(|(_, fence)| *fence == tag).map(|(name, _)| *name),
+                };
+                current = Some((name, Vec::new()));
+            }
         }
-        
-        profile_config.profiles.insert(name.to_string(), Config::default());
-        profile_config.active_profile = name.to_string();
-        self.save_profile_config(&profile_config)?;
-        
-        println!("✅ Created and switched to profile '{}'", name);
-        println!("💡 Configure it with: lexi config set <key> <value>");
-        Ok(())
+        // An unterminated last block still counts
+        if let Some((Some(name), lines)) = current {
+            if !files.iter().any(|(file, _)| *file == name) {
+                files.push((name, lines.join("\n")));
+            }
+        }
+
+        files
     }
 
-    fn delete_profile(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut profile_config = self.load_profile_config();
-        
-        if name == "default" {
-            eprintln!("❌ Cannot delete the default profile");
-            process::exit(1);
+    // Normalizes a web response into html, css and javascript blocks, linking the page to its stylesheet and script
+    fn extract_web_bundle(&self, response: &str, target: &str) -> String {
+        let mut files = self.web_files(response);
+        if files.is_empty() {
+            files.push(("index.html", self.extract_code_from_response(response, target)));
         }
-        
-        if !profile_config.profiles.contains_key(name) {
-            eprintln!("❌ Profile '{}' does not exist", name);
-            process::exit(1);
+
+        let has_file = |files: &[(&str, String)], name: &str| files.iter().any(|(file, _)| *file == name);
+        let (has_css, has_js) = (has_file(&files, "style.css"), has_file(&files, "app.js"));
+        if let Some((_, html)) = files.iter_mut().find(|(name, _)| *name == "index.html") {
+            if has_css && !html.contains("style.css") {
+                let link = "  <link rel=\"stylesheet\" href=\"style.css\">\n";
+                match html.find("</head>") {
+                    Some(index) => html.insert_str(index, link),
+                    None => html.insert_str(0, link.trim_start()),
+                }
+            }
+            if has_js && !html.contains("app.js") {
+                let script = "  <script src=\"app.js\" defer></script>\n";
+                match html.find("</body>") {
+                    Some(index) => html.insert_str(index, script),
+                    None => html.push_str(&format!("\n{}", script.trim())),
+                }
+            }
         }
-        
-        profile_config.profiles.remove(name);
-        
-        // Switch to default if we deleted the active profile
-        if profile_config.active_profile == name {
-            profile_config.active_profile = "default".to_string();
-            println!("🔄 Switched back to 'default' profile");
+
+        WEB_FILES
+            .iter()
+            .filter_map(|(name, fence)| {
+                let (_, content) = files.iter().find(|(file, _)| file == name)?;
+                Some(format!("```{}\n{}\n```", fence, content.trim_end()))
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    // Writes generated code, merging in edits made to the previous output since it was
+    // generated. Returns the number of merge conflicts left in the file.
+    fn write_output(&self, output_file: &std::path::Path, generated_code: &str, overwrite: bool) -> Result<usize, Box<dyn std::error::Error>> {
+        let base_file = self.generated_base_path(output_file);
+        let existing = fs::read_to_string(output_file).ok();
+        let base = fs::read_to_string(&base_file).ok();
+
+        let mut generated = generated_code.to_string();
+        if !generated.ends_with('\n') {
+            generated.push('\n');
         }
-        
-        self.save_profile_config(&profile_config)?;
-        println!("✅ Deleted profile '{}'", name);
-        Ok(())
+
+        let mut result = generated.clone();
+        let mut conflicts = 0;
+        if let (Some(existing), false) = (&existing, overwrite) {
+            if base.as_deref() != Some(existing.as_str()) {
+                generated = self.restore_keep_regions(existing, &generated);
+                result = match &base {
+                    Some(base) => match diffy::merge(base, existing, &generated) {
+                        Ok(merged) => {
+                            println!("🔀 Merged your edits to {} with the new generation", output_file.display());
+                            merged
+                        }
+                        Err(conflicted) => {
+                            conflicts = conflicted.lines().filter(|line| line.starts_with("<<<<<<<")).count();
+                            conflicted
+                        }
+                    },
+                    // Output written before base tracking has nothing to merge against, so it is replaced,
+                    // with a copy kept in case it was edited by hand
+                    None if *existing != generated => {
+                        let backup = PathBuf::from(format!("{}.bak", base_file.display()));
+                        if let Some(parent) = backup.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::write(&backup, existing)?;
+                        println!("💾 No earlier generation of {} to merge with; saved it to {} and replaced it", output_file.display(), backup.display());
+                        generated.clone()
+                    }
+                    None => generated.clone(),
+                };
+            }
+        }
+
+        fs::write(output_file, result)?;
+        if let Some(parent) = base_file.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&base_file, generated)?;
+
+        Ok(conflicts)
     }
 
-    fn show_current_profile(&self) {
-        let profile_config = self.load_profile_config();
-        println!("📍 Current profile: {}", profile_config.active_profile);
+    fn generated_base_path(&self, output_file: &std::path::Path) -> PathBuf {
+        let dir = output_file.parent().unwrap_or(std::path::Path::new(""));
+        let file_name = output_file.file_name().and_then(|name| name.to_str()).unwrap_or("output");
+        dir.join(".lexi").join(file_name)
     }
 
-    fn set_profile_config(&self, profile_name: &str, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
-        let mut profile_config = self.load_profile_config();
-        
-        if !profile_config.profiles.contains_key(profile_name) {
-            eprintln!("❌ Profile '{}' does not exist. Create it first with: lexi profile create {}", profile_name, profile_name);
-            process::exit(1);
+    // Regions between `lexi:keep begin <name>` and `lexi:keep end` lines, in any comment syntax
+    fn keep_regions(&self, code: &str) -> Vec<(String, String)> {
+        let mut regions = Vec::new();
+        let mut current: Option<(String, Vec<&str>)> = None;
+
+        for line in code.lines() {
+            if let Some((_, rest)) = line.split_once("lexi:keep begin") {
+                let name = rest.trim().trim_end_matches("*/").trim_end_matches("-->").trim();
+                let name = if name.is_empty() { format!("#{}", regions.len() + 1) } else { name.to_string() };
+                current = Some((name, vec![line]));
+            } else if let Some((_, lines)) = current.as_mut() {
+                lines.push(line);
+                if line.contains("lexi:keep end") {
+                    let (name, lines) = current.take().unwrap();
+                    regions.push((name, lines.join("\n")));
+                }
+            }
         }
-        
-        let mut config = profile_config.profiles[profile_name].clone();
-        
-        match key {
-            "provider" => config.provider = value.to_string(),
-            "model" => config.model = value.to_string(),
-            "api_key" => config.api_key = value.to_string(),
-            "base_url" => config.base_url = value.to_string(),
-            "temperature" => config.temperature = value.parse().unwrap_or(0.1),
-            "max_tokens" => config.max_tokens = value.parse().unwrap_or(2000),
-            _ => {
-                eprintln!("❌ Unknown config key: {}", key);
-                process::exit(1);
+
+        regions
+    }
+
+    fn restore_keep_regions(&self, existing: &str, generated: &str) -> String {
+        let mut restored = generated.to_string();
+        let generated_regions = self.keep_regions(generated);
+
+        for (name, kept) in self.keep_regions(existing) {
+            match generated_regions.iter().find(|(generated_name, _)| *generated_name == name) {
+                Some((_, replaced)) => restored = restored.replacen(replaced.as_str(), &kept, 1),
+                None => {
+                    println!("📌 Kept region '{}' has no marker in the new output; appended at the end", name);
+                    if !restored.ends_with('\n') {
+                        restored.push('\n');
+                    }
+                    restored.push('\n');
+                    restored.push_str(&kept);
+                    restored.push('\n');
+                }
             }
         }
-        
-        profile_config.profiles.insert(profile_name.to_string(), config);
-        self.save_profile_config(&profile_config)?;
-        
-        println!("✅ Set {} = {} for profile '{}'", key, value, profile_name);
-        
-        if key == "api_key" {
-            println!("🔐 API key saved securely");
+
+        restored
+    }
+
+    fn get_default_output_file(&self, input: &str, target: &str) -> String {
+        let base_name = std::path::Path::new(input)
+            .file_stem()
+            .unwrap()
+            .to_str()
+            .unwrap();
+
+        // A web page is a directory of files
+        if self.layout(target) == OutputLayout::WebBundle {
+            return std::path::Path::new(base_name).join("index.html").to_string_lossy().to_string();
+        }
+
+        format!("{}{}", base_name, self.target(target).map(|target| target.extension.as_str()).unwrap_or_default())
+    }
+
+    fn target_from_extension(&self, file: &str) -> Option<String> {
+        let file_name = std::path::Path::new(file).file_name()?.to_str()?;
+
+        // The longest matching extension wins, so app.compose.yaml is not taken for plain YAML;
+        // a bare name like `Dockerfile` matches its extension too
+        self.targets
+            .iter()
+            .filter(|target| !target.extension.is_empty())
+            .filter(|target| file_name.ends_with(&target.extension) || file_name == target.extension.trim_start_matches('.'))
+            .min_by_key(|target| std::cmp::Reverse(target.extension.len()))
+            .map(|target| target.name.clone())
+    }
+
+    async fn generate_code_with_llm(
+        &self,
+        lexi_content: &str,
+        job: &CompileJob<'_>,
+        signatures: &[&LexiSignature],
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let (system_prompt, user_prompt) = self.build_prompt(lexi_content, job.target, signatures, job.style.as_deref())?;
+        let response = self.call_provider(&system_prompt, &user_prompt, &job.overrides).await?;
+
+        if self.layout(job.target) == OutputLayout::WebBundle {
+            return Ok(self.extract_web_bundle(&response, job.target));
+        }
+        Ok(self.extract_code_from_response(&response, job.target))
+    }
+
+    async fn call_provider(&self, system_prompt: &str, user_prompt: &str, overrides: &ModelOverrides) -> Result<String, Box<dyn std::error::Error>> {
+        let mut config = match &overrides.profile {
+            Some(name) => match self.load_profile_config().profiles.get(name) {
+                Some(config) => config.clone(),
+                None => return Err(format!("Profile '{}' does not exist. Create it first with: lexi profile create {}", name, name).into()),
+            },
+            None => self.load_config(),
+        };
+        if let Some(model) = &overrides.model {
+            config.model = model.clone();
+        }
+        if let Some(temperature) = overrides.temperature {
+            config.temperature = temperature;
+        }
+        if let Some(max_tokens) = overrides.max_tokens {
+            config.max_tokens = max_tokens;
+        }
+
+        if config.api_key.is_empty() && config.provider != "local" {
+            return Err("No API key configured. Run: lexi config set api_key <your-key>".into());
+        }
+
+        match config.provider.as_str() {
+            "openai" => self.call_openai(system_prompt, user_prompt, &config).await,
+            "anthropic" => self.call_anthropic(system_prompt, user_prompt, &config).await,
+            "local" => self.call_ollama(system_prompt, user_prompt, &config).await,
+            "azure" => self.call_azure(system_prompt, user_prompt, &config).await,
+            _ => Err(format!("Unsupported provider: {}", config.provider).into()),
+        }
+    }
+
+    fn build_prompt(
+        &self,
+        lexi_content: &str,
+        target: &str,
+        signatures: &[&LexiSignature],
+        style: Option<&str>,
+    ) -> Result<(String, String), Box<dyn std::error::Error>> {
+        let signatures = if signatures.is_empty() {
+            String::new()
+        } else {
+            let mut section = "Required signatures (a hard contract: use exactly these function names, parameter names, parameter order and types):".to_string();
+            for signature in signatures {
+                section.push_str(&format!("\n- {}", self.render_signature(target, signature)));
+            }
+            section
+        };
+        let style = style.map(|style| format!("Follow this style guide:\n{}", style)).unwrap_or_default();
+        let rules = self.target(target)?.prompt.clone().unwrap_or_default();
+        // Frontmatter is blanked out only to keep line numbers; the model doesn't need the empty lines
+        let blocks = lexi_content.trim_start_matches(['\n', '\r']);
+        let variables = [
+            ("target", target),
+            ("target_rules", rules.as_str()),
+            ("blocks", blocks),
+            ("signatures", signatures.as_str()),
+            ("style", style.as_str()),
+        ];
+
+        let system_prompt = self.render_template(&self.load_template(target, "system")?, &variables)?;
+        let user_prompt = self.render_template(&self.load_template(target, "user")?, &variables)?;
+        Ok((system_prompt, user_prompt))
+    }
+
+    fn show_prompt(&self, input: &str, target: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let lexi_content = match fs::read_to_string(input) {
+            Ok(content) => content,
+            Err(_) => {
+                eprintln!("❌ Error: File '{}' not found", input);
+                process::exit(1);
+            }
+        };
+        let target = &self.file_target(target, &lexi_content, &self.project)?;
+        let output_file = self.get_default_output_file(input, target);
+        let output_dir = std::path::Path::new(&output_file).parent().unwrap_or(std::path::Path::new(""));
+
+        let options = CompileOptions {
+            check_examples: false,
+            overwrite: false,
+            source_map: false,
+            inline_refs: false,
+            overrides: ModelOverrides::default(),
+            schema: None,
+            allow_dangerous: false,
+        };
+        let job = self.prepare_job(input, &lexi_content, target, output_dir, false, &options)?;
+        let blocks = self.parse_blocks(&job.lexi_content);
+        let signatures: Vec<&LexiSignature> = blocks.iter().flat_map(|block| &block.signatures).collect();
+        let (system_prompt, user_prompt) =
+            self.build_prompt(&self.prompt_content(&job, &blocks, &options), target, &signatures, job.style.as_deref())?;
+
+        for (kind, prompt) in [("system", system_prompt), ("user", user_prompt)] {
+            let source = match self.template_path(target, kind)? {
+                (_, Some(path)) => path.display().to_string(),
+                (name, None) => format!("built-in {}", name),
+            };
+            println!("===== {} prompt ({}) =====", kind, source);
+            println!("{}\n", prompt);
         }
-        
         Ok(())
     }
 
-    fn show_config_init(&self) {
-        println!("🚀 Setting up Lexi configuration...");
-        println!();
-        println!("Available providers:");
-        println!("  • openai (GPT-4, GPT-3.5)");
-        println!("  • anthropic (Claude)");
-        println!("  • local (Ollama)");
-        println!("  • azure (Azure OpenAI)");
-        println!();
-        println!("Example setup:");
-        println!("  lexi config set provider openai");
-        println!("  lexi config set model gpt-4");
-        println!("  lexi config set api_key sk-...");
-        println!();
-        println!("For local Ollama:");
-        println!("  lexi config set provider local");
-        println!("  lexi config set model codellama");
-        println!("  lexi config set base_url http://localhost:11434");
+    fn builtin_template(&self, name: &str) -> Option<&'static str> {
+        match name {
+            "default.system.md" => Some(include_str!("../prompts/default.system.md")),
+            "default.user.md" => Some(include_str!("../prompts/default.user.md")),
+            "sql.system.md" => Some(include_str!("../prompts/sql.system.md")),
+            "sql.user.md" => Some(include_str!("../prompts/sql.user.md")),
+            "mongodb.system.md" => Some(include_str!("../prompts/mongodb.system.md")),
+            "mongodb.user.md" => Some(include_str!("../prompts/mongodb.user.md")),
+            "redis.system.md" => Some(include_str!("../prompts/redis.system.md")),
+            "redis.user.md" => Some(include_str!("../prompts/redis.user.md")),
+            "web.system.md" => Some(include_str!("../prompts/web.system.md")),
+            "jupyter.system.md" => Some(include_str!("../prompts/jupyter.system.md")),
+            "regex.system.md" => Some(include_str!("../prompts/regex.system.md")),
+            _ => None,
+        }
+    }
+
+    // Project templates win over ~/.lexi/prompts, and a target's own template over the default one
+    fn template_path(&self, target: &str, kind: &str) -> Result<(String, Option<PathBuf>), Box<dyn std::error::Error>> {
+        let mut dirs = vec![PathBuf::from(".lexi").join("prompts")];
+        if let Some(home) = dirs::home_dir() {
+            dirs.push(home.join(".lexi").join("prompts"));
+        }
+        self.find_template(&dirs, target, kind)
+    }
+
+    // The most specific template name wins; for each name the directories are tried in order, then the built-ins
+    fn find_template(&self, dirs: &[PathBuf], target: &str, kind: &str) -> Result<(String, Option<PathBuf>), Box<dyn std::error::Error>> {
+        // A dialect like sql:postgres tries sql.postgres, then sql, then default
+        let parts: Vec<&str> = target.split(':').collect();
+        let mut names: Vec<String> = (1..=parts.len()).rev().map(|end| format!("{}.{}.md", parts[..end].join("."), kind)).collect();
+        names.push(format!("default.{}.md", kind));
+
+        for name in names {
+            for dir in dirs {
+                let path = dir.join(&name);
+                if path.exists() {
+                    return Ok((name, Some(path)));
+                }
+            }
+            if self.builtin_template(&name).is_some() {
+                return Ok((name, None));
+            }
+        }
+        Err(format!("No {} prompt template found for target '{}'", kind, target).into())
+    }
+
+    fn load_template(&self, target: &str, kind: &str) -> Result<String, Box<dyn std::error::Error>> {
+        match self.template_path(target, kind)? {
+            (_, Some(path)) => fs::read_to_string(&path).map_err(|error| format!("Failed to read prompt template {}: {}", path.display(), error).into()),
+            (name, None) => Ok(self.builtin_template(&name).unwrap_or_default().to_string()),
+        }
+    }
+
+    fn render_template(&self, template: &str, variables: &[(&str, &str)]) -> Result<String, Box<dyn std::error::Error>> {
+        let mut rendered = String::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..].find("}}").ok_or("Prompt template has an unclosed {{")? + start;
+            let name = rest[start + 2..end].trim();
+            let value = variables
+                .iter()
+                .find(|(variable, _)| *variable == name)
+                .map(|(_, value)| value.trim_end_matches('\n'))
+                .ok_or_else(|| format!("Prompt template uses unknown variable {{{{{}}}}}", name))?;
+
+            // An empty variable alone on its line takes the line, and the blank line after it,
+            // with it; blank lines in the values themselves are left alone
+            let line_start = rest[..start].rfind('\n').map_or(0, |newline| newline + 1);
+            let line_end = rest[end + 2..].find('\n').map(|newline| end + 2 + newline);
+            let alone = rest[line_start..start].trim().is_empty()
+                && rest[end + 2..line_end.unwrap_or(rest.len())].trim().is_empty()
+                && (line_start > 0 || rendered.is_empty() || rendered.ends_with('\n'));
+            if value.is_empty() && alone {
+                rendered.push_str(&rest[..line_start]);
+                rest = line_end.map_or("", |line_end| &rest[line_end + 1..]);
+                if (rendered.is_empty() || rendered.ends_with("\n\n")) && rest.starts_with('\n') {
+                    rest = &rest[1..];
+                }
+                continue;
+            }
+
+            rendered.push_str(&rest[..start]);
+            rendered.push_str(value);
+            rest = &rest[end + 2..];
+        }
+        rendered.push_str(rest);
+        Ok(rendered.trim().to_string())
+    }
+
+    async fn call_openai(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+        let client = reqwest::Client::new();
+        
+        let request = OpenAIRequest {
+            model: config.model.clone(),
+            messages: vec![
+                Message {
+                    role: "system".to_string(),
+                    content: system_prompt.to_string(),
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: user_prompt.to_string(),
+                },
+            ],
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+        };
+
+        let response = client
+            .post("https://api.openai.com/v1/chat/completions")
+            .header("Authorization", format!("Bearer {}", config.api_key))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(format!("OpenAI API error: {}", error_text).into());
+        }
+
+        let openai_response: OpenAIResponse = response.json().await?;
+        Ok(openai_response.choices[0].message.content.clone())
+    }
+
+    async fn call_anthropic(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+        let client = reqwest::Client::new();
+        
+        let request = AnthropicRequest {
+            model: config.model.clone(),
+            max_tokens: config.max_tokens,
+            messages: vec![Message {
+                role: "user".to_string(),
+                content: format!("{}\n\n{}", system_prompt, user_prompt),
+            }],
+        };
+
+        let response = client
+            .post("https://api.anthropic.com/v1/messages")
+            .header("x-api-key", &config.api_key)
+            .header("Content-Type", "application/json")
+            .header("anthropic-version", "2023-06-01")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(format!("Anthropic API error: {}", error_text).into());
+        }
+
+        let anthropic_response: AnthropicResponse = response.json().await?;
+        Ok(anthropic_response.content[0].text.clone())
+    }
+
+    async fn call_ollama(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+        let client = reqwest::Client::new();
+        let base_url = if config.base_url.is_empty() {
+            "http://localhost:11434"
+        } else {
+            &config.base_url
+        };
+
+        let request = OllamaRequest {
+            model: config.model.clone(),
+            prompt: format!("{}\n\n{}", system_prompt, user_prompt),
+            stream: false,
+        };
+
+        let response = client
+            .post(format!("{}/api/generate", base_url))
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(format!("Ollama API error: {}", error_text).into());
+        }
+
+        let ollama_response: OllamaResponse = response.json().await?;
+        Ok(ollama_response.response)
+    }
+
+    async fn call_azure(&self, system_prompt: &str, user_prompt: &str, config: &Config) -> Result<String, Box<dyn std::error::Error>> {
+        if config.base_url.is_empty() {
+            return Err("Azure baseUrl not configured. Set: lexi config set base_url https://your-resource.openai.azure.com".into());
+        }
+
+        let client = reqwest::Client::new();
+        let url = format!(
+            "{}/openai/deployments/{}/chat/completions?api-version=2023-12-01-preview",
+            config.base_url, config.model
+        );
+
+        let request = OpenAIRequest {
+            model: config.model.clone(),
+            messages: vec![
+                Message {
+                    role: "system".to_string(),
+                    content: system_prompt.to_string(),
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: user_prompt.to_string(),
+                },
+            ],
+            temperature: config.temperature,
+            max_tokens: config.max_tokens,
+        };
+
+        let response = client
+            .post(&url)
+            .header("api-key", &config.api_key)
+            .header("Content-Type", "application/json")
+            .json(&request)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            return Err(format!("Azure API error: {}", error_text).into());
+        }
+
+        let azure_response: OpenAIResponse = response.json().await?;
+        Ok(azure_response.choices[0].message.content.clone())
+    }
+
+    // Removes markdown code fences, longest tags first so `js` doesn't eat `json`
+    fn strip_fences(&self, response: &str, target: &str) -> String {
+        let mut fences = self.target(target).map(|target| target.fences.clone()).unwrap_or_default();
+        fences.sort_by_key(|fence| std::cmp::Reverse(fence.len()));
+        let mut code = response.to_string();
+        for fence in fences {
+            code = code.replace(&format!("```{}", fence), "");
+        }
+        code.replace("```", "").trim_matches(|c| c == '\n' || c == '\r').trim_end().to_string()
+    }
+
+    // The body of a response's fenced block, without prose around it; the block runs to the last closing
+    // fence so fence lines inside the file are kept. None when the response has no fence
+    fn fenced_body(&self, response: &str) -> Option<String> {
+        let lines: Vec<&str> = response.lines().collect();
+        let open = lines.iter().position(|line| line.trim_start().starts_with("```"))?;
+        let ticks = lines[open].trim_start().chars().take_while(|&c| c == '`').count();
+        let close = (open + 1..lines.len())
+            .rev()
+            .find(|&index| {
+                let line = lines[index].trim();
+                line.len() >= ticks && line.chars().all(|c| c == '`')
+            })
+            .unwrap_or(lines.len());
+        Some(lines[open + 1..close].join("\n").trim_end().to_string())
+    }
+
+    fn extract_code_from_response(&self, response: &str, target: &str) -> String {
+        // Remove markdown code blocks and leading/trailing whitespace
+        let code = self.strip_fences(response, target).trim().to_string();
+
+        // Find first line that looks like code
+        let lines: Vec<&str> = code.lines().collect();
+        let mut start_index = 0;
+        let mut end_index = lines.len();
+
+        // Configuration keeps its leading lines, such as a Dockerfile's `# syntax=` directive
+        let declarative = self.target(target).is_ok_and(|target| target.declarative);
+        for (i, line) in lines.iter().enumerate().filter(|_| !declarative) {
+            let trimmed = line.trim();
+            if trimmed.starts_with("function ")
+                || trimmed.starts_with("def ")
+                || trimmed.starts_with("class ")
+                || trimmed.starts_with("import ")
+                || trimmed.starts_with("const ")
+                || trimmed.starts_with("let ")
+                || trimmed.starts_with("var ")
+                || trimmed.starts_with("#include")
+                || trimmed.starts_with("package ")
+                || trimmed.starts_with("use ")
+                || trimmed.starts_with("#[")
+                || trimmed.starts_with("export ")
+                || trimmed.starts_with("interface ")
+                || trimmed.starts_with("#!")
+                || trimmed.starts_with("<?php")
+                || trimmed.starts_with("using ")
+                || trimmed.starts_with("namespace ")
+                || trimmed.starts_with("fun ")
+                || trimmed.starts_with("func ")
+                || trimmed.starts_with("struct ")
+                || trimmed.starts_with("require ")
+                || trimmed.starts_with("module ")
+                || trimmed.starts_with("# frozen_string_literal")
+            {
+                start_index = i;
+                break;
+            }
+        }
+
+        // Find last line that looks like code
+        for (i, line) in lines.iter().enumerate().rev() {
+            let trimmed = line.trim();
+            if !trimmed.is_empty() && !trimmed.starts_with("Note:") && !trimmed.starts_with("Example:") {
+                end_index = i + 1;
+                break;
+            }
+        }
+
+        lines[start_index..end_index].join("\n")
+    }
+
+    fn parse_frontmatter(&self, lexi_content: &str) -> Result<(Frontmatter, String), Box<dyn std::error::Error>> {
+        let lines: Vec<&str> = lexi_content.lines().collect();
+        if lines.first().map(|line| line.trim()) != Some("---") {
+            return Ok((Frontmatter::default(), lexi_content.to_string()));
+        }
+
+        let mut frontmatter = Frontmatter::default();
+        for (index, line) in lines.iter().enumerate().skip(1) {
+            let trimmed = line.trim();
+            if trimmed == "---" {
+                // Blank out the header so line numbers in the body stay the same
+                let body = format!("{}{}", "\n".repeat(index + 1), lines[index + 1..].join("\n"));
+                return Ok((frontmatter, body));
+            }
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+
+            let line_number = index + 1;
+            let Some((key, value)) = trimmed.split_once(':') else {
+                return Err(format!("Frontmatter line {}: expected `key: value`", line_number).into());
+            };
+            let value = value.trim().trim_matches('"').to_string();
+            let invalid = |kind: &str| format!("Frontmatter line {}: {} must be {}", line_number, key.trim(), kind);
+
+            match key.trim() {
+                "target" => frontmatter.target = Some(value),
+                "profile" => frontmatter.overrides.profile = Some(value),
+                "model" => frontmatter.overrides.model = Some(value),
+                "temperature" => frontmatter.overrides.temperature = Some(value.parse().map_err(|_| invalid("a number"))?),
+                "max_tokens" => frontmatter.overrides.max_tokens = Some(value.parse().map_err(|_| invalid("a whole number"))?),
+                "style" => frontmatter.style = Some(value),
+                "schema" => frontmatter.schema = Some(value),
+                other => return Err(format!("Frontmatter line {}: unknown key '{}'", line_number, other).into()),
+            }
+        }
+
+        Err("Frontmatter is missing its closing ---".into())
+    }
+
+    fn parse_blocks(&self, lexi_content: &str) -> Vec<LexiBlock> {
+        let mut blocks = Vec::new();
+        let mut current: Option<LexiBlock> = None;
+
+        for (index, line) in lexi_content.lines().enumerate() {
+            let line_number = index + 1;
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() {
+                blocks.extend(current.take());
+                continue;
+            }
+            if trimmed.starts_with('#') || trimmed.starts_with('@') {
+                continue;
+            }
+
+            let block = current.get_or_insert_with(|| LexiBlock {
+                start_line: line_number,
+                end_line: line_number,
+                text: String::new(),
+                examples: Vec::new(),
+                signatures: Vec::new(),
+                regex_cases: Vec::new(),
+            });
+            block.end_line = line_number;
+            if !block.text.is_empty() {
+                block.text.push('\n');
+            }
+            block.text.push_str(trimmed);
+
+            if let Some(example) = self.parse_example(trimmed, line_number) {
+                block.examples.push(example);
+            }
+            if let Some(signature) = self.parse_signature(trimmed) {
+                block.signatures.push(signature);
+            }
+            if let Some(case) = self.parse_regex_case(trimmed, line_number) {
+                block.regex_cases.push(case);
+            }
+        }
+
+        blocks.extend(current.take());
+        blocks
+    }
+
+    fn parse_signature(&self, line: &str) -> Option<LexiSignature> {
+        let rest = line.strip_prefix("fn ")?;
+        let (name, rest) = rest.split_once('(')?;
+        let (params, returns) = rest.rsplit_once(')')?;
+
+        let name = name.trim();
+        if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+
+        let mut parsed_params = Vec::new();
+        for param in self.split_top_level(params) {
+            let (param_name, param_type) = param.split_once(':')?;
+            parsed_params.push((param_name.trim().to_string(), param_type.trim().to_string()));
+        }
+
+        let returns = match returns.trim().strip_prefix("->") {
+            Some(returns) => returns.trim().to_string(),
+            None if returns.trim().is_empty() => "void".to_string(),
+            None => return None,
+        };
+
+        Some(LexiSignature {
+            name: name.to_string(),
+            params: parsed_params,
+            returns,
+            text: line.to_string(),
+        })
+    }
+
+    // Splits on commas that are not nested inside brackets
+    fn split_top_level(&self, text: &str) -> Vec<String> {
+        let mut parts = Vec::new();
+        let mut depth = 0;
+        let mut current = String::new();
+
+        for c in text.chars() {
+            match c {
+                '<' | '(' | '[' | '{' => depth += 1,
+                '>' | ')' | ']' | '}' => depth -= 1,
+                ',' if depth == 0 => {
+                    parts.push(current.trim().to_string());
+                    current.clear();
+                    continue;
+                }
+                _ => {}
+            }
+            current.push(c);
+        }
+
+        if !current.trim().is_empty() {
+            parts.push(current.trim().to_string());
+        }
+        parts
+    }
+
+    fn parse_types(&self, lexi_content: &str) -> Result<Vec<LexiType>, Box<dyn std::error::Error>> {
+        let mut types = Vec::new();
+        let mut current: Option<LexiType> = None;
+
+        for (index, line) in lexi_content.lines().enumerate() {
+            let mut body = line.trim();
+
+            if current.is_none() {
+                let Some((name, rest)) = body.strip_prefix("type ").and_then(|rest| rest.split_once('{')) else {
+                    continue;
+                };
+                let name = name.trim();
+                if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    continue;
+                }
+                current = Some(LexiType {
+                    name: name.to_string(),
+                    fields: Vec::new(),
+                });
+                body = rest;
+            }
+
+            let (fields, closed) = match body.split_once('}') {
+                Some((fields, _)) => (fields, true),
+                None => (body, false),
+            };
+
+            let declared = current.as_mut().unwrap();
+            for field in self.split_top_level(fields) {
+                let Some((name, lexi_type)) = field.split_once(':') else {
+                    return Err(format!("Line {}: expected `name: type` in type {}, found `{}`", index + 1, declared.name, field).into());
+                };
+                let name = name.trim();
+                declared.fields.push(LexiField {
+                    name: name.trim_end_matches('?').to_string(),
+                    lexi_type: lexi_type.trim().to_string(),
+                    optional: name.ends_with('?'),
+                });
+            }
+
+            if closed {
+                types.extend(current.take());
+            }
+        }
+
+        if let Some(unclosed) = current {
+            return Err(format!("Type {} is missing its closing }}", unclosed.name).into());
+        }
+        Ok(types)
+    }
+
+    fn parse_imports(&self, lexi_content: &str) -> Vec<String> {
+        lexi_content
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix("@import "))
+            .map(|path| path.trim().trim_matches('"').to_string())
+            .filter(|path| !path.is_empty())
+            .collect()
+    }
+
+    // All .lxi files imported by `input`, directly or not, in the order they must be compiled
+    fn resolve_imports(&self, input: &std::path::Path) -> Result<Vec<PathBuf>, Box<dyn std::error::Error>> {
+        let mut order = Vec::new();
+        self.visit_imports(input, &mut Vec::new(), &mut order)?;
+        // The input itself is visited last
+        order.pop();
+        Ok(order)
+    }
+
+    fn visit_imports(&self, file: &std::path::Path, stack: &mut Vec<PathBuf>, order: &mut Vec<PathBuf>) -> Result<(), Box<dyn std::error::Error>> {
+        let file = fs::canonicalize(file).map_err(|_| format!("Imported file '{}' not found", file.display()))?;
+        let name = |path: &PathBuf| path.file_name().unwrap_or_default().to_string_lossy().to_string();
+
+        if let Some(position) = stack.iter().position(|visiting| *visiting == file) {
+            let cycle: Vec<String> = stack[position..].iter().chain([&file]).map(name).collect();
+            return Err(format!("Import cycle: {}", cycle.join(" -> ")).into());
+        }
+        if order.contains(&file) {
+            return Ok(());
+        }
+
+        let content = fs::read_to_string(&file)?;
+        let dir = file.parent().unwrap_or(std::path::Path::new("")).to_path_buf();
+        stack.push(file.clone());
+        for import in self.parse_imports(&content) {
+            self.visit_imports(&dir.join(import), stack, order)?;
+        }
+        stack.pop();
+
+        order.push(file);
+        Ok(())
+    }
+
+    fn parse_example(&self, line: &str, line_number: usize) -> Option<LexiExample> {
+        let rest = line.strip_prefix("Example:")?;
+        let (call, expected) = rest.split_once("->")?;
+        let (call, expected) = (call.trim(), expected.trim());
+
+        if call.is_empty() || expected.is_empty() {
+            return None;
+        }
+
+        Some(LexiExample {
+            call: call.to_string(),
+            expected: expected.to_string(),
+            line: line_number,
+        })
+    }
+
+    // `Should match: text` or `Should not match: text`; backticks keep surrounding spaces
+    fn parse_regex_case(&self, line: &str, line_number: usize) -> Option<RegexCase> {
+        let (rest, should_match) = match (line.strip_prefix("Should match:"), line.strip_prefix("Should not match:")) {
+            (Some(rest), _) => (rest, true),
+            (_, Some(rest)) => (rest, false),
+            _ => return None,
+        };
+
+        let rest = rest.trim();
+        if rest.is_empty() {
+            return None;
+        }
+        let text = rest.strip_prefix('`').and_then(|text| text.strip_suffix('`')).unwrap_or(rest);
+
+        Some(RegexCase {
+            text: text.to_string(),
+            should_match,
+            line: line_number,
+        })
+    }
+
+    async fn decompile(&self, input: &str, output: Option<&str>, target: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        if !std::path::Path::new(input).exists() {
+            eprintln!("❌ Error: File '{}' not found", input);
+            process::exit(1);
+        }
+
+        let target = match target.map(|target| self.canonical_target(target)).transpose() {
+            Ok(target) => target,
+            Err(error) => {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        };
+        let Some(target) = target.or_else(|| self.target_from_extension(input)) else {
+            eprintln!("❌ Error: Cannot infer the language of '{}'. Pass --target", input);
+            process::exit(1);
+        };
+        let target = target.as_str();
+
+        let default_output = std::path::Path::new(input).with_extension("lxi");
+        let output_file = match output {
+            Some(output) => PathBuf::from(output),
+            None if default_output.exists() => {
+                eprintln!(
+                    "❌ Error: '{}' already exists. Pass --output to choose another file",
+                    default_output.display()
+                );
+                process::exit(1);
+            }
+            None => default_output,
+        };
+
+        let source = fs::read_to_string(input)?;
+        if source.trim().is_empty() {
+            eprintln!("❌ Error: Source file is empty");
+            process::exit(1);
+        }
+
+        println!("📚 Lexi v1.0.0 - Decompiling {}...", input);
+        println!("🤖 Describing code with AI...");
+
+        let file_name = std::path::Path::new(input)
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or(input);
+        let system_prompt = format!(
+            "You are Lexi, a code analyst that describes existing {} code as a Lexi program: plain English blocks that compile back into equivalent code.
+
+Rules:
+1. Output only the Lexi program, no explanations or markdown
+2. Write one block per top-level function or class, separated by a single blank line, in source order
+3. Start each block with \"Create a function named <name> that ...\" or \"Create a class named <name> that ...\" and keep the original names and parameters
+4. Describe inputs, outputs, edge cases and error handling precisely enough to regenerate equivalent code
+5. Add lines like \"Example: name(args) -> result\" when behavior is easy to show with literal values
+6. Begin with a # comment line naming the original file
+
+Source language: {}",
+            target, target
+        );
+        let user_prompt = format!("Describe {} as a Lexi program:\n\n{}", file_name, source);
+
+        let response = self.call_provider(&system_prompt, &user_prompt, &ModelOverrides::default()).await?;
+        let lexi_program = response
+            .lines()
+            .filter(|line| !line.trim_start().starts_with("```"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let lexi_program = format!("{}\n", lexi_program.trim());
+
+        let blocks = self.parse_blocks(&lexi_program);
+        fs::write(&output_file, &lexi_program)?;
+        println!("✅ Described {} as {} block(s) in {}", file_name, blocks.len(), output_file.display());
+        println!("💡 Compile it back with: lexi compile {} --target {}", output_file.display(), target);
+
+        Ok(())
+    }
+
+    async fn refine(
+        &self,
+        file: &str,
+        instruction: &str,
+        target: Option<&str>,
+        yes: bool,
+        allow_dangerous: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if !std::path::Path::new(file).exists() {
+            eprintln!("❌ Error: File '{}' not found", file);
+            process::exit(1);
+        }
+
+        let target = match target.map(|target| self.canonical_target(target)).transpose() {
+            Ok(target) => target,
+            Err(error) => {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        };
+        let Some(target) = target.or_else(|| self.target_from_extension(file)) else {
+            eprintln!("❌ Error: Cannot infer the target language of '{}'. Pass --target", file);
+            process::exit(1);
+        };
+        let target = target.as_str();
+        if self.layout(target) != OutputLayout::File {
+            eprintln!("❌ Error: Cannot refine {} output; change the .lxi description and compile it again", target);
+            process::exit(1);
+        }
+
+        let current_code = fs::read_to_string(file)?;
+        println!("📚 Lexi v1.0.0 - Refining {}...", file);
+        println!("🤖 Applying \"{}\" with AI...", instruction);
+
+        let system_prompt = format!(
+            "You are Lexi, a code editor that applies English change requests to existing {} code.
+
+Rules:
+1. Return the complete updated file, no explanations or markdown
+2. Change only what the request requires; keep every other line exactly as it is
+3. Preserve existing names, formatting, comments and manual edits
+4. Follow the style of the existing code
+
+Target language: {}",
+            target, target
+        );
+        let user_prompt = format!(
+            "Current contents of {}:
+
+{}
+
+Change request: {}",
+            file, current_code, instruction
+        );
+
+        let response = self.call_provider(&system_prompt, &user_prompt, &ModelOverrides::default()).await?;
+        // The whole file comes back, so keep all of the fenced block: headers, license blocks, "use strict"
+        let mut refined_code = self
+            .fenced_body(&response)
+            .unwrap_or_else(|| response.trim_matches(|c| c == '\n' || c == '\r').trim_end().to_string());
+        if current_code.ends_with('\n') && !refined_code.ends_with('\n') {
+            refined_code.push('\n');
+        }
+
+        if refined_code == current_code {
+            println!("✅ No changes needed");
+            return Ok(());
+        }
+
+        let patch = diffy::create_patch(&current_code, &refined_code);
+        let formatter = if std::io::IsTerminal::is_terminal(&std::io::stdout()) {
+            diffy::PatchFormatter::new().with_color()
+        } else {
+            diffy::PatchFormatter::new()
+        };
+        println!();
+        println!("{}", formatter.fmt_patch(&patch));
+
+        // Only commands the refinement adds need approval
+        let existing: Vec<String> = self
+            .find_dangerous(&current_code, target)?
+            .iter()
+            .filter_map(|found| found.split_once(": ").map(|(_, matched)| matched.to_string()))
+            .collect();
+        let added: Vec<String> = self
+            .find_dangerous(&refined_code, target)?
+            .into_iter()
+            .filter(|found| found.split_once(": ").is_some_and(|(_, matched)| !existing.iter().any(|known| known == matched)))
+            .collect();
+        if !added.is_empty() && !allow_dangerous {
+            eprintln!("❌ Refusing to apply these changes: they add destructive commands");
+            for found in &added {
+                eprintln!("   ⚠️  {}", found);
+            }
+            eprintln!("   Review the instruction, or pass --allow-dangerous to apply them anyway");
+            process::exit(1);
+        }
+
+        if !yes && !self.confirm("Apply these changes?")? {
+            println!("❎ Changes discarded");
+            return Ok(());
+        }
+
+        fs::write(file, refined_code)?;
+        println!("✅ Updated {}", file);
+        Ok(())
+    }
+
+    fn confirm(&self, question: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        use std::io::Write;
+
+        print!("{} [y/N] ", question);
+        std::io::stdout().flush()?;
+
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    async fn watch(&self, target: Option<&str>, run: bool, debounce_ms: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let project = &self.project;
+        let default_target = match self.project_target(project, target) {
+            Ok(target) => target,
+            Err(error) => {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        };
+        let source_dir = std::path::Path::new(&project.source_dir);
+        let build_dir = std::path::Path::new(&project.build_dir);
+        let debounce = std::time::Duration::from_millis(debounce_ms);
+
+        if !source_dir.is_dir() {
+            eprintln!("❌ Error: Source directory '{}' not found", project.source_dir);
+            process::exit(1);
+        }
+        fs::create_dir_all(build_dir)?;
+
+        println!("👀 Lexi v1.0.0 - Watching {} for changes ({}, Ctrl+C to stop)...", source_dir.display(), default_target);
+
+        // Web pages are served once from the build directory; each recompile shows up on reload
+        let _server = if run && self.layout(&default_target) == OutputLayout::WebBundle {
+            let (url, server) = self.serve_static(build_dir)?;
+            println!("🌐 Serving {} at {}", build_dir.display(), url);
+            Some(server)
+        } else {
+            None
+        };
+
+        let mut known = self.scan_sources(source_dir)?;
+        // Files seen changing, with the time of their latest change
+        let mut pending: HashMap<PathBuf, std::time::Instant> = HashMap::new();
+
+        loop {
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+            let current = self.scan_sources(source_dir)?;
+            for (path, modified) in &current {
+                if known.get(path) != Some(modified) {
+                    pending.insert(path.clone(), std::time::Instant::now());
+                }
+            }
+            known = current;
+
+            let settled: Vec<PathBuf> = pending
+                .iter()
+                .filter(|(_, changed)| changed.elapsed() >= debounce)
+                .map(|(path, _)| path.clone())
+                .collect();
+
+            for path in settled {
+                pending.remove(&path);
+                if !known.contains_key(&path) {
+                    continue;
+                }
+
+                // Files imported by others are compiled as library modules
+                let mut imported = Vec::new();
+                for source in known.keys() {
+                    let content = fs::read_to_string(source).unwrap_or_default();
+                    let source_dir = source.parent().unwrap_or(std::path::Path::new(""));
+                    for import in self.parse_imports(&content) {
+                        imported.extend(fs::canonicalize(source_dir.join(import)).ok());
+                    }
+                }
+
+                if let Err(error) = self.recompile_changed(&path, target, project, run, &imported).await {
+                    eprintln!("❌ {}: {}", path.display(), error);
+                }
+            }
+        }
+    }
+
+    fn scan_sources(&self, source_dir: &std::path::Path) -> Result<HashMap<PathBuf, std::time::SystemTime>, Box<dyn std::error::Error>> {
+        let mut files = Vec::new();
+        self.find_files(source_dir, ".lxi", &mut files)?;
+
+        let mut sources = HashMap::new();
+        for file in files {
+            if file.to_string_lossy().ends_with(".test.lxi") {
+                continue;
+            }
+            // Files can disappear between listing and stat while an editor saves
+            if let Ok(modified) = fs::metadata(&file).and_then(|metadata| metadata.modified()) {
+                sources.insert(file, modified);
+            }
+        }
+
+        Ok(sources)
+    }
+
+    async fn recompile_changed(
+        &self,
+        path: &std::path::Path,
+        cli_target: Option<&str>,
+        project: &ProjectConfig,
+        run: bool,
+        imported: &[PathBuf],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let input = path.to_string_lossy();
+        let lexi_content = fs::read_to_string(path)?;
+        if lexi_content.trim().is_empty() {
+            return Ok(());
+        }
+
+        let build_dir = std::path::Path::new(&project.build_dir);
+        let target = &self.file_target(cli_target, &lexi_content, project)?;
+
+        // Outputs mirror the source tree, so src/a/main.lxi and src/b/main.lxi don't collide
+        let relative_dir = path
+            .parent()
+            .and_then(|dir| dir.strip_prefix(&project.source_dir).ok())
+            .unwrap_or(std::path::Path::new(""));
+        let output_dir = build_dir.join(relative_dir);
+        fs::create_dir_all(&output_dir)?;
+        let output_file = output_dir.join(self.get_default_output_file(&input, target));
+        let output_name = output_file.to_string_lossy();
+        println!();
+        println!("🔄 {} changed, recompiling...", input);
+
+        let previous = fs::read_to_string(&output_file).unwrap_or_default();
+        let options = CompileOptions {
+            check_examples: true,
+            overwrite: false,
+            source_map: false,
+            inline_refs: false,
+            overrides: ModelOverrides::default(),
+            schema: None,
+            allow_dangerous: false,
+        };
+        // Imports are compiled into the same output directory, as `lexi compile` does
+        for dependency in &self.resolve_imports(path)? {
+            self.compile_dependency(dependency, target, &output_dir, &options).await?;
+        }
+        let library = imported.contains(&fs::canonicalize(path)?);
+        let job = self.prepare_job(&input, &lexi_content, target, &output_dir, library, &options)?;
+        let (generated_code, checks_failed) = self.generate_checked(&job, &options).await?;
+        let dangerous = self.find_dangerous(&generated_code, target)?;
+        if !dangerous.is_empty() {
+            return Err(format!(
+                "Not writing {}: it contains destructive commands ({}). Compile it with --allow-dangerous instead",
+                output_name,
+                dangerous.join("; ")
+            )
+            .into());
+        }
+        let conflicts = self.write_generated(&output_file, &generated_code, &job, false)?;
+        println!("✅ Compiled to {}", output_name);
+        self.print_change_summary(&previous, &fs::read_to_string(&output_file)?);
+
+        if conflicts > 0 {
+            eprintln!("⚠️  {} conflict(s) merging your edits; resolve the <<<<<<< markers", conflicts);
+        } else if checks_failed {
+            eprintln!("❌ Generated code still fails its checks after {} attempts", MAX_GENERATION_ATTEMPTS);
+        } else if run && self.layout(target) == OutputLayout::WebBundle {
+            println!("🌐 Reload {} in the browser to see the changes", output_name);
+        } else if run {
+            println!("🚀 Running {}...", output_name);
+            let run_options = RunOptions { args: Vec::new(), capture: false };
+            self.run_output(&output_name, target, &run_options)?;
+        }
+
+        Ok(())
+    }
+
+    fn print_change_summary(&self, previous: &str, current: &str) {
+        const MAX_DIFF_LINES: usize = 40;
+
+        let patch = diffy::create_patch(previous, current);
+        let mut added = 0;
+        let mut removed = 0;
+        let mut lines = Vec::new();
+        for hunk in patch.hunks() {
+            for line in hunk.lines() {
+                match line {
+                    diffy::Line::Insert(text) => {
+                        added += 1;
+                        lines.push(format!("   + {}", text.trim_end()));
+                    }
+                    diffy::Line::Delete(text) => {
+                        removed += 1;
+                        lines.push(format!("   - {}", text.trim_end()));
+                    }
+                    diffy::Line::Context(_) => {}
+                }
+            }
+        }
+
+        if added == 0 && removed == 0 {
+            println!("   (no code changes)");
+            return;
+        }
+
+        println!("📝 +{} -{} lines", added, removed);
+        for line in lines.iter().take(MAX_DIFF_LINES) {
+            println!("{}", line);
+        }
+        if lines.len() > MAX_DIFF_LINES {
+            println!("   ... {} more changed lines", lines.len() - MAX_DIFF_LINES);
+        }
+    }
+
+    async fn run_tests(&self, files: &[String], target: Option<&str>, report: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let project = &self.project;
+        let target = match self.project_target(project, target) {
+            Ok(target) => target,
+            Err(error) => {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        };
+        let build_dir = std::path::Path::new(&project.build_dir);
+
+        let mut test_files: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+        if test_files.is_empty() {
+            self.find_files(std::path::Path::new(&project.source_dir), ".test.lxi", &mut test_files)?;
+            test_files.sort();
+        }
+        if test_files.is_empty() {
+            eprintln!("❌ No .test.lxi files found in {}", project.source_dir);
+            process::exit(1);
+        }
+
+        fs::create_dir_all(build_dir)?;
+        println!("🧪 Lexi v1.0.0 - Running {} test file(s) for {}...", test_files.len(), target);
+
+        let mut results = Vec::new();
+        for test_file in &test_files {
+            let file_name = test_file.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            let Some(stem) = file_name.strip_suffix(".test.lxi") else {
+                eprintln!("❌ Error: Test file '{}' must end with .test.lxi", test_file.display());
+                process::exit(1);
+            };
+
+            let module_file = self.get_default_output_file(&format!("{}.lxi", stem), &target);
+            let framework = self.target(&target)?.test_framework.and_then(|runner| self.test_framework(runner, stem, &module_file));
+            let Some(framework) = framework else {
+                eprintln!("❌ Error: No test framework known for target '{}'", target);
+                process::exit(1);
+            };
+
+            println!("📄 {} ({})", test_file.display(), framework.name);
+            let started = std::time::Instant::now();
+            let (passed, output) = match self.generate_and_run_test(test_file, build_dir, &module_file, &target, &framework).await {
+                Ok(outcome) => outcome,
+                Err(error) => (false, error.to_string()),
+            };
+
+            if passed {
+                println!("   ✅ passed");
+            } else {
+                println!("   ❌ failed");
+                for line in output.lines().rev().take(20).collect::<Vec<_>>().into_iter().rev() {
+                    println!("      {}", line);
+                }
+            }
+
+            let cases = self.parse_test_cases(&output, framework.case_pattern)?;
+            results.push(TestResult {
+                file: test_file.display().to_string(),
+                framework: framework.name,
+                passed,
+                cases,
+                output,
+                duration: started.elapsed(),
+            });
+        }
+
+        let report_path = report
+            .map(PathBuf::from)
+            .unwrap_or_else(|| build_dir.join("test-results.xml"));
+        fs::write(&report_path, self.build_junit_report(&results))?;
+
+        let failed = results.iter().filter(|result| !result.passed).count();
+        println!();
+        println!("📊 {} passed, {} failed", results.len() - failed, failed);
+        println!("📝 JUnit report written to {}", report_path.display());
+
+        if failed > 0 {
+            process::exit(1);
+        }
+        Ok(())
+    }
+
+    async fn generate_and_run_test(
+        &self,
+        test_file: &std::path::Path,
+        build_dir: &std::path::Path,
+        module_file: &str,
+        target: &str,
+        framework: &TestFramework,
+    ) -> Result<(bool, String), Box<dyn std::error::Error>> {
+        let module_path = build_dir.join(module_file);
+        if !module_path.exists() {
+            return Err(format!(
+                "{} not found; compile the module into {} first",
+                module_path.display(),
+                build_dir.display()
+            )
+            .into());
+        }
+
+        let description = fs::read_to_string(test_file)?;
+        let module_source = fs::read_to_string(&module_path)?;
+
+        let system_prompt = format!(
+            "You are Lexi, a test generator that converts English descriptions of expected behavior into {} unit tests for {} code.
+
+Rules:
+1. Generate only the test code, no explanations or markdown
+2. Write one test per described behavior with a descriptive name
+3. {}
+4. Only call functions that exist in the module under test
+5. Cover the edge cases the description mentions
+
+Test framework: {}",
+            framework.name, target, framework.import_rule, framework.name
+        );
+        let user_prompt = format!(
+            "Module under test ({}):
+
+{}
+
+Write tests for this expected behavior:
+
+{}",
+            module_file, module_source, description
+        );
+
+        let response = self.call_provider(&system_prompt, &user_prompt, &ModelOverrides::default()).await?;
+        let mut test_code = self.extract_code_from_response(&response, target);
+        if framework.inline {
+            test_code = format!("{}\n\n{}\n", module_source, test_code);
+        }
+        fs::write(build_dir.join(&framework.test_file), test_code)?;
+
+        let mut output = String::new();
+        for command in &framework.commands {
+            let result = process::Command::new(&command[0])
+                .args(&command[1..])
+                .current_dir(build_dir)
+                .stdin(process::Stdio::null())
+                .output()
+                .map_err(|error| format!("Could not run {}: {}", command[0], error))?;
+
+            output.push_str(&String::from_utf8_lossy(&result.stdout));
+            output.push_str(&String::from_utf8_lossy(&result.stderr));
+            if !result.status.success() {
+                return Ok((false, output));
+            }
+        }
+
+        Ok((true, output))
+    }
+
+    fn test_framework(&self, runner: TestRunner, stem: &str, module_file: &str) -> Option<TestFramework> {
+        let args = |parts: &[&str]| parts.iter().map(|part| part.to_string()).collect::<Vec<String>>();
+
+        match runner {
+            TestRunner::Jest => {
+                let test_file = format!("{}.test.js", stem);
+                Some(TestFramework {
+                    name: "jest",
+                    import_rule: format!("Load the module under test with require('./{}')", module_file),
+                    inline: false,
+                    commands: vec![args(&["npx", "--yes", "jest", "--ci", "--verbose", "--rootDir", ".", &test_file])],
+                    test_file,
+                    case_pattern: r"^\s*(?:✓|√) (?P<pass>.+?)(?: \(\d+ ms\))?$|^\s*(?:✕|×) (?P<fail>.+?)(?: \(\d+ ms\))?$",
+                })
+            }
+            TestRunner::Pytest => {
+                let test_file = format!("test_{}.py", stem);
+                Some(TestFramework {
+                    name: "pytest",
+                    import_rule: format!("Import the module under test with `from {} import *`", stem),
+                    inline: false,
+                    commands: vec![args(&["python", "-m", "pytest", "-q", "-rA", &test_file])],
+                    test_file,
+                    case_pattern: r"^PASSED \S+::(?P<pass>\S+)|^FAILED \S+::(?P<fail>\S+)",
+                })
+            }
+            TestRunner::RustTest => {
+                let test_file = format!("{}_test.rs", stem);
+                let binary = format!("{}_test", stem);
+                Some(TestFramework {
+                    name: "rustc --test",
+                    import_rule: "Output only a #[cfg(test)] mod tests block starting with use super::*; it is appended to the module source".to_string(),
+                    inline: true,
+                    commands: vec![
+                        args(&["rustc", "--edition", "2021", "--test", &test_file, "-o", &binary]),
+                        args(&[&format!("./{}", binary)]),
+                    ],
+                    test_file,
+                    case_pattern: r"^test (?P<pass>\S+) \.\.\. ok$|^test (?P<fail>\S+) \.\.\. FAILED$",
+                })
+            }
+            TestRunner::GoTest => {
+                let test_file = format!("{}_test.go", stem);
+                Some(TestFramework {
+                    name: "go test",
+                    import_rule: "Use the same package as the module under test and the standard testing package".to_string(),
+                    inline: false,
+                    commands: vec![args(&["go", "test", "-v", module_file, &test_file])],
+                    test_file,
+                    case_pattern: r"^\s*--- PASS: (?P<pass>\S+)|^\s*--- FAIL: (?P<fail>\S+)",
+                })
+            }
+            TestRunner::JUnit => {
+                let mut chars = stem.chars();
+                let capitalized = chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>())?;
+                let class_name = format!("{}Test", capitalized);
+                let test_file = format!("{}.java", class_name);
+                let junit_jar = std::env::var("JUNIT_JAR").unwrap_or_else(|_| "junit-platform-console-standalone.jar".to_string());
+                Some(TestFramework {
+                    name: "JUnit",
+                    import_rule: format!("Use JUnit 5 (org.junit.jupiter.api) in a class named {} in the default package", class_name),
+                    inline: false,
+                    commands: vec![
+                        args(&["javac", "-cp", &junit_jar, module_file, &test_file]),
+                        args(&["java", "-jar", &junit_jar, "--class-path", ".", "--select-class", &class_name]),
+                    ],
+                    test_file,
+                    case_pattern: r"(?P<pass>\S+\([^)]*\)) ✔|(?P<fail>\S+\([^)]*\)) ✘",
+                })
+            }
+        }
+    }
+
+    fn find_files(&self, dir: &std::path::Path, suffix: &str, found: &mut Vec<PathBuf>) -> std::io::Result<()> {
+        if !dir.is_dir() {
+            return Ok(());
+        }
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                self.find_files(&path, suffix, found)?;
+            } else if path.to_str().is_some_and(|name| name.ends_with(suffix)) {
+                found.push(path);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_test_cases(&self, output: &str, case_pattern: &str) -> Result<Vec<(String, bool)>, Box<dyn std::error::Error>> {
+        let pattern = regex::Regex::new(case_pattern)?;
+        let cases = output
+            .lines()
+            .filter_map(|line| {
+                let captures = pattern.captures(line.trim_end())?;
+                match (captures.name("pass"), captures.name("fail")) {
+                    (Some(name), _) => Some((name.as_str().to_string(), true)),
+                    (_, Some(name)) => Some((name.as_str().to_string(), false)),
+                    _ => None,
+                }
+            })
+            .collect();
+        Ok(cases)
+    }
+
+    fn build_junit_report(&self, results: &[TestResult]) -> String {
+        // A file whose tests never ran (say, a compile error) is reported as one failed case
+        let case_counts = |result: &TestResult| match result.cases.len() {
+            0 => (1, usize::from(!result.passed)),
+            count => (count, result.cases.iter().filter(|(_, passed)| !passed).count()),
+        };
+        let (tests, failures) = results.iter().map(case_counts).fold((0, 0), |(tests, failures), (count, failed)| {
+            (tests + count, failures + failed)
+        });
+        let total_time: f64 = results.iter().map(|result| result.duration.as_secs_f64()).sum();
+
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuites name=\"lexi\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+            tests, failures, total_time
+        ));
+
+        for result in results {
+            let (tests, failures) = case_counts(result);
+            let file = self.xml_escape(&result.file);
+            xml.push_str(&format!(
+                "  <testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" time=\"{:.3}\">\n",
+                file,
+                tests,
+                failures,
+                result.duration.as_secs_f64()
+            ));
+
+            if result.cases.is_empty() {
+                xml.push_str(&format!("    <testcase classname=\"{}\" name=\"{}\">\n", file, result.framework));
+                if !result.passed {
+                    xml.push_str(&format!(
+                        "      <failure message=\"{} tests did not run\">{}</failure>\n",
+                        result.framework,
+                        self.xml_escape(&result.output)
+                    ));
+                }
+                xml.push_str("    </testcase>\n");
+            }
+            for (name, passed) in &result.cases {
+                if *passed {
+                    xml.push_str(&format!("    <testcase classname=\"{}\" name=\"{}\"/>\n", file, self.xml_escape(name)));
+                } else {
+                    xml.push_str(&format!(
+                        "    <testcase classname=\"{}\" name=\"{}\">\n      <failure message=\"test failed\"/>\n    </testcase>\n",
+                        file,
+                        self.xml_escape(name)
+                    ));
+                }
+            }
+
+            xml.push_str(&format!("    <system-out>{}</system-out>\n", self.xml_escape(&result.output)));
+            xml.push_str("  </testsuite>\n");
+        }
+
+        xml.push_str("</testsuites>\n");
+        xml
+    }
+
+    fn xml_escape(&self, text: &str) -> String {
+        text.replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;")
+            .replace('"', "&quot;")
+    }
+
+    fn comment_prefix(&self, target: &str) -> String {
+        self.target(target).map(|target| target.comment.clone()).unwrap_or_else(|_| "//".to_string())
+    }
+
+    fn build_trace_instructions(&self, blocks: &[LexiBlock], target: &str) -> String {
+        let mut instructions = format!(
+            "The description has {} numbered blocks. Immediately before the code for each block, write a comment line `{} lexi:block <number>`:\n",
+            blocks.len(),
+            self.comment_prefix(target)
+        );
+
+        for (index, block) in blocks.iter().enumerate() {
+            instructions.push_str(&format!(
+                "{}. {}\n",
+                index + 1,
+                block.text.lines().next().unwrap_or_default()
+            ));
+        }
+
+        instructions
+    }
+
+    fn parse_block_marker(&self, line: &str) -> Option<usize> {
+        let (_, rest) = line.split_once("lexi:block")?;
+        rest.trim().trim_end_matches("*/").trim_end_matches("-->").trim().parse().ok()
+    }
+
+    fn resolve_block_markers(&self, code: &str, blocks: &[LexiBlock], source_name: &str, target: &str, inline_refs: bool) -> (String, Vec<BlockMapping>) {
+        let mut lines: Vec<String> = Vec::new();
+        // (block number, index of its first output line)
+        let mut starts: Vec<(usize, usize)> = Vec::new();
+
+        for line in code.lines() {
+            match self.parse_block_marker(line) {
+                Some(number) if (1..=blocks.len()).contains(&number) => {
+                    starts.push((number, lines.len()));
+                    if inline_refs {
+                        let indent = &line[..line.len() - line.trim_start().len()];
+                        lines.push(format!(
+                            "{}{} {}:{}",
+                            indent,
+                            self.comment_prefix(target),
+                            source_name,
+                            blocks[number - 1].start_line
+                        ));
+                    }
+                }
+                _ => lines.push(line.to_string()),
+            }
+        }
+
+        let mut line_offsets = Vec::with_capacity(lines.len() + 1);
+        let mut offset = 0;
+        for line in &lines {
+            line_offsets.push(offset);
+            offset += line.len() + 1;
+        }
+        line_offsets.push(offset);
+
+        let mut mappings = Vec::new();
+        for (position, &(number, start)) in starts.iter().enumerate() {
+            let mut end = starts.get(position + 1).map_or(lines.len(), |&(_, next)| next);
+            while end > start && lines[end - 1].trim().is_empty() {
+                end -= 1;
+            }
+            if end == start {
+                continue;
+            }
+
+            let block = &blocks[number - 1];
+            mappings.push(BlockMapping {
+                block: number,
+                source: SourceSpan {
+                    start_line: block.start_line,
+                    end_line: block.end_line,
+                },
+                output: OutputSpan {
+                    start_line: start + 1,
+                    end_line: end,
+                    start_byte: line_offsets[start],
+                    end_byte: line_offsets[end] - 1,
+                },
+            });
+        }
+
+        (lines.join("\n"), mappings)
+    }
+
+    fn init_project(&self, project_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let project_path = std::path::Path::new(project_name);
+        
+        if project_path.exists() {
+            eprintln!("❌ Error: Directory '{}' already exists", project_name);
+            process::exit(1);
+        }
+
+        // Create project structure
+        fs::create_dir_all(project_path.join("src"))?;
+        fs::create_dir_all(project_path.join("build"))?;
+
+        // Create sample .lxi file
+        let sample_lexi = r#"# Sample Lexi Program
+# Write your logic in plain English below
+
+Create a function that takes a list of numbers and returns only the even ones greater than 10
+
+Create a function to check if a string is a palindrome, ignoring case and spaces
+
+Build a simple web server that responds with "Hello, World!" on GET requests to the root path
+"#;
+
+        fs::write(project_path.join("src/main.lxi"), sample_lexi)?;
+
+        // Create lexi.config.json
+        let config = serde_json::json!({
+            "name": project_name,
+            "version": "1.0.0",
+            "defaultTarget": "javascript",
+            "sourceDir": "src",
+            "buildDir": "build",
+            "targets": {
+                "javascript": { "extension": ".js" },
+                "python": { "extension": ".py" },
+                "java": { "extension": ".java" }
+            }
+        });
+
+        fs::write(
+            project_path.join("lexi.config.json"),
+            serde_json::to_string_pretty(&config)?,
+        )?;
+
+        // Create README
+        let readme = format!(
+            r#"# {}
+
+A Lexi project - code generated from English descriptions using AI.
+
+## Getting Started
+
+1. Configure your LLM provider:
+   ```bash
+   lexi config set provider openai
+   lexi config set model gpt-4
+   lexi config set api_key sk-your-key-here
+   ```
+
+2. Write your logic in `src/main.lxi`
+
+3. Compile:
+   ```bash
+   lexi compile src/main.lxi --target javascript
+   ```
+
+4. Run:
+   ```bash
+   node build/main.js
+   ```
+
+## Commands
+
+- `lexi compile <file.lxi>` - Compile to JavaScript (default)
+- `lexi compile <file.lxi> --target python` - Compile to Python  
+- `lexi compile <file.lxi> --run` - Compile and run immediately
+- `lexi config list` - Show current configuration
+
+## Project Structure
+
+```
+{}/
+├── src/           # Your .lxi source files
+├── build/         # Compiled output
+├── lexi.config.json
+└── README.md
+```
+"#,
+            project_name, project_name
+        );
+
+        fs::write(project_path.join("README.md"), readme)?;
+
+        println!("📚 Created new Lexi project: {}", project_name);
+        println!("📁 Project structure:");
+        println!("   {}/", project_name);
+        println!("   ├── src/main.lxi");
+        println!("   ├── build/");
+        println!("   ├── lexi.config.json");
+        println!("   └── README.md");
+        println!();
+        println!("Next steps:");
+        println!("   cd {}", project_name);
+        println!("   lexi config init  # Configure your AI provider");
+        println!("   lexi compile src/main.lxi");
+
+        Ok(())
+    }
+
+    fn set_config(&self, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut config = self.load_config();
+
+        match key {
+            "provider" => config.provider = value.to_string(),
+            "model" => config.model = value.to_string(),
+            "api_key" => config.api_key = value.to_string(),
+            "base_url" => config.base_url = value.to_string(),
+            "temperature" => config.temperature = value.parse().unwrap_or(0.1),
+            "max_tokens" => config.max_tokens = value.parse().unwrap_or(2000),
+            _ => {
+                eprintln!("❌ Unknown config key: {}", key);
+                process::exit(1);
+            }
+        }
+
+        self.save_config(&config)?;
+        println!("✅ Set {} = {}", key, value);
+
+        if key == "api_key" {
+            println!("🔐 API key saved securely to ~/.lexi/config.json");
+        }
+
+        Ok(())
+    }
+
+    fn list_config(&self) {
+        let profile_config = self.load_profile_config();
+        let default_config = Config::default();
+        let config = profile_config.profiles
+            .get(&profile_config.active_profile)
+            .unwrap_or(&default_config);
+            
+        println!("🔧 Lexi Configuration (Profile: {}):", profile_config.active_profile);
+        println!("   provider: {}", config.provider);
+        println!("   model: {}", config.model);
+        println!(
+            "   api_key: {}",
+            if config.api_key.is_empty() {
+                "(not set)".to_string()
+            } else {
+                format!("{}...", &config.api_key[..8.min(config.api_key.len())])
+            }
+        );
+        println!("   base_url: {}", if config.base_url.is_empty() { "(not set)" } else { &config.base_url });
+        println!("   temperature: {}", config.temperature);
+        println!("   max_tokens: {}", config.max_tokens);
+    }
+
+    fn list_profiles(&self) {
+        let profile_config = self.load_profile_config();
+        println!("👥 Available Profiles:");
+        
+        for (name, config) in &profile_config.profiles {
+            let active_marker = if name == &profile_config.active_profile { " (active)" } else { "" };
+            println!("   • {}{} - {} ({})", name, active_marker, config.provider, config.model);
+        }
+    }
+
+    fn switch_profile(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut profile_config = self.load_profile_config();
+        
+        if !profile_config.profiles.contains_key(name) {
+            eprintln!("❌ Profile '{}' does not exist. Create it first with: lexi profile create {}", name, name);
+            process::exit(1);
+        }
+        
+        profile_config.active_profile = name.to_string();
+        self.save_profile_config(&profile_config)?;
+        
+        println!("✅ Switched to profile '{}'", name);
+        Ok(())
+    }
+
+    fn create_profile(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut profile_config = self.load_profile_config();
+        
+        if profile_config.profiles.contains_key(name) {
+            println!("⚠️  Profile '{}' already exists", name);
+            return Ok(());
+        }
+        
+        profile_config.profiles.insert(name.to_string(), Config::default());
+        profile_config.active_profile = name.to_string();
+        self.save_profile_config(&profile_config)?;
+        
+        println!("✅ Created and switched to profile '{}'", name);
+        println!("💡 Configure it with: lexi config set <key> <value>");
+        Ok(())
+    }
+
+    fn delete_profile(&self, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut profile_config = self.load_profile_config();
+        
+        if name == "default" {
+            eprintln!("❌ Cannot delete the default profile");
+            process::exit(1);
+        }
+        
+        if !profile_config.profiles.contains_key(name) {
+            eprintln!("❌ Profile '{}' does not exist", name);
+            process::exit(1);
+        }
+        
+        profile_config.profiles.remove(name);
+        
+        // Switch to default if we deleted the active profile
+        if profile_config.active_profile == name {
+            profile_config.active_profile = "default".to_string();
+            println!("🔄 Switched back to 'default' profile");
+        }
+        
+        self.save_profile_config(&profile_config)?;
+        println!("✅ Deleted profile '{}'", name);
+        Ok(())
+    }
+
+    fn show_current_profile(&self) {
+        let profile_config = self.load_profile_config();
+        println!("📍 Current profile: {}", profile_config.active_profile);
+    }
+
+    fn set_profile_config(&self, profile_name: &str, key: &str, value: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut profile_config = self.load_profile_config();
+        
+        if !profile_config.profiles.contains_key(profile_name) {
+            eprintln!("❌ Profile '{}' does not exist. Create it first with: lexi profile create {}", profile_name, profile_name);
+            process::exit(1);
+        }
+        
+        let mut config = profile_config.profiles[profile_name].clone();
+        
+        match key {
+            "provider" => config.provider = value.to_string(),
+            "model" => config.model = value.to_string(),
+            "api_key" => config.api_key = value.to_string(),
+            "base_url" => config.base_url = value.to_string(),
+            "temperature" => config.temperature = value.parse().unwrap_or(0.1),
+            "max_tokens" => config.max_tokens = value.parse().unwrap_or(2000),
+            _ => {
+                eprintln!("❌ Unknown config key: {}", key);
+                process::exit(1);
+            }
+        }
+        
+        profile_config.profiles.insert(profile_name.to_string(), config);
+        self.save_profile_config(&profile_config)?;
+        
+        println!("✅ Set {} = {} for profile '{}'", key, value, profile_name);
+        
+        if key == "api_key" {
+            println!("🔐 API key saved securely");
+        }
+        
+        Ok(())
+    }
+
+    fn show_config_init(&self) {
+        println!("🚀 Setting up Lexi configuration...");
+        println!();
+        println!("Available providers:");
+        println!("  • openai (GPT-4, GPT-3.5)");
+        println!("  • anthropic (Claude)");
+        println!("  • local (Ollama)");
+        println!("  • azure (Azure OpenAI)");
+        println!();
+        println!("Example setup:");
+        println!("  lexi config set provider openai");
+        println!("  lexi config set model gpt-4");
+        println!("  lexi config set api_key sk-...");
+        println!();
+        println!("For local Ollama:");
+        println!("  lexi config set provider local");
+        println!("  lexi config set model codellama");
+        println!("  lexi config set base_url http://localhost:11434");
+    }
+
+    fn run_output(&self, output_file: &str, target: &str, run_options: &RunOptions) -> Result<i32, Box<dyn std::error::Error>> {
+        if self.target(target)?.declarative {
+            println!("⚠️  {} output is not a program; lexi never runs it. Use {} with your own tooling", target, output_file);
+            return Ok(0);
+        }
+        if self.layout(target) == OutputLayout::WebBundle {
+            let output_path = std::path::Path::new(output_file);
+            let root = output_path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+            let (mut url, server) = self.serve_static(root)?;
+            let page = output_path.file_name().and_then(|name| name.to_str()).unwrap_or_default();
+            if page != "index.html" {
+                url.push_str(page);
+            }
+            println!("🌐 Serving {} at {} (Ctrl+C to stop)", root.display(), url);
+            server.join().map_err(|_| "web server thread panicked")?;
+            return Ok(0);
+        }
+
+        let runners = &self.target(target)?.runners;
+        let runner = runners
+            .iter()
+            .find(|runner| runner.split("&&").all(|step| self.command_available(step.split_whitespace().next().unwrap_or_default())));
+
+        let mut command = match runner {
+            Some(runner) => {
+                // Earlier steps build the program; the last one runs it
+                let mut steps = self.expand_command(runner, std::path::Path::new(output_file))?;
+                let run = steps.pop().unwrap_or_default();
+                for step in steps {
+                    let status = process::Command::new(&step[0]).args(&step[1..]).status()?;
+                    if !status.success() {
+                        eprintln!("❌ Build step `{}` failed", step.join(" "));
+                        return Ok(status.code().unwrap_or(1));
+                    }
+                }
+                let mut command = process::Command::new(&run[0]);
+                command.args(&run[1..]);
+                command
+            }
+            // Without a TypeScript runner, transpile and run the JavaScript
+            None if target == "typescript" && self.command_available("tsc") => {
+                let mut command = process::Command::new("node");
+                command.arg(self.transpile_typescript(output_file)?);
+                command
+            }
+            None if runners.is_empty() => {
+                println!("⚠️  Auto-run not supported for {} yet", target);
+                return Ok(0);
+            }
+            None => {
+                let programs: Vec<String> = runners.iter().map(|runner| self.runner_programs(runner)).collect();
+                println!("⚠️  Running {} needs one of: {}", target, programs.join(", "));
+                return Ok(0);
+            }
+        };
+        command.args(&run_options.args);
+
+        let started = std::time::Instant::now();
+        let status = if run_options.capture {
+            self.run_captured(&mut command, output_file)?
+        } else {
+            command.status()?
+        };
+        let elapsed = started.elapsed();
+
+        // A child killed by a signal has no exit code; report it as a failure
+        let exit_code = status.code().unwrap_or(1);
+        if status.success() {
+            println!("⏱️  Finished in {:.2}s", elapsed.as_secs_f64());
+        } else {
+            eprintln!("❌ Program exited with code {} after {:.2}s", exit_code, elapsed.as_secs_f64());
+        }
+
+        Ok(exit_code)
+    }
+
+    // Splits a runner or validator into `&&`-separated steps, filling in {file}, {build} and {stem}.
+    // Without a {file} placeholder the path is appended to the last step.
+    fn expand_command(&self, command: &str, file: &std::path::Path) -> Result<Vec<Vec<String>>, Box<dyn std::error::Error>> {
+        let build_dir = file.parent().unwrap_or(std::path::Path::new(".")).join(".lexi").join("build");
+        let stem = file.file_stem().unwrap_or_default().to_string_lossy().trim_start_matches('.').to_string();
+        let file_path = file.to_string_lossy().to_string();
+        if command.contains("{build}") {
+            fs::create_dir_all(&build_dir)?;
+        }
+
+        let mut steps: Vec<Vec<String>> = command
+            .split("&&")
+            .map(|step| {
+                step.split_whitespace()
+                    .map(|word| {
+                        word.replace("{file}", &file_path)
+                            .replace("{build}", &build_dir.to_string_lossy())
+                            .replace("{stem}", &stem)
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .filter(|step| !step.is_empty())
+            .collect();
+        if !command.contains("{file}") {
+            if let Some(last) = steps.last_mut() {
+                last.push(file_path);
+            }
+        }
+        Ok(steps)
+    }
+
+    // Serves a directory on localhost from a background thread, returning its URL
+    fn serve_static(&self, root: &std::path::Path) -> Result<(String, std::thread::JoinHandle<()>), Box<dyn std::error::Error>> {
+        use std::io::{Read, Write};
+
+        // Prefer a memorable port, falling back to any free one
+        let listener = match (8000..8010).find_map(|port| std::net::TcpListener::bind(("127.0.0.1", port)).ok()) {
+            Some(listener) => listener,
+            None => std::net::TcpListener::bind(("127.0.0.1", 0))?,
+        };
+        let url = format!("http://localhost:{}/", listener.local_addr()?.port());
+
+        fn respond(mut stream: std::net::TcpStream, root: &std::path::Path) -> std::io::Result<()> {
+            // Browsers open connections they may never send on
+            stream.set_read_timeout(Some(std::time::Duration::from_secs(5)))?;
+            let mut buffer = [0u8; 8192];
+            // A request can arrive in pieces; the headers end with a blank line
+            let mut read = 0;
+            while read < buffer.len() && !buffer[..read].windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buffer[read..])? {
+                    0 => break,
+                    count => read += count,
+                }
+            }
+            let request = String::from_utf8_lossy(&buffer[..read]);
+            let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+            let method = request_line.next().unwrap_or_default();
+            let path = request_line.next().unwrap_or("/").split(['?', '#']).next().unwrap_or("/");
+
+            // Never serve outside the root or its hidden files, such as .lexi; a `..` segment counts as hidden
+            let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+            let hidden = segments.iter().any(|segment| segment.starts_with('.'));
+            let mut file = segments.iter().fold(root.to_path_buf(), |file, segment| file.join(segment));
+            if file.is_dir() {
+                file.push("index.html");
+            }
+
+            let content_type = match file.extension().and_then(|extension| extension.to_str()).unwrap_or_default() {
+                "html" => "text/html; charset=utf-8",
+                "css" => "text/css; charset=utf-8",
+                "js" => "text/javascript; charset=utf-8",
+                "json" => "application/json",
+                "svg" => "image/svg+xml",
+                "png" => "image/png",
+                "jpg" | "jpeg" => "image/jpeg",
+                "ico" => "image/x-icon",
+                _ => "application/octet-stream",
+            };
+            let (status, content_type, body) = match (method, fs::read(&file)) {
+                ("GET" | "HEAD", Ok(body)) if !hidden => ("200 OK", content_type, body),
+                ("GET" | "HEAD", _) => ("404 Not Found", "text/plain; charset=utf-8", b"Not found".to_vec()),
+                _ => ("405 Method Not Allowed", "text/plain; charset=utf-8", b"Method not allowed".to_vec()),
+            };
+
+            write!(
+                stream,
+                "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nCache-Control: no-store\r\nConnection: close\r\n\r\n",
+                status,
+                content_type,
+                body.len()
+            )?;
+            if method != "HEAD" {
+                stream.write_all(&body)?;
+            }
+            Ok(())
+        }
+
+        // One thread per connection, so a slow client never holds up the others
+        let root = std::sync::Arc::new(root.to_path_buf());
+        let server = std::thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let root = std::sync::Arc::clone(&root);
+                std::thread::spawn(move || respond(stream, &root));
+            }
+        });
+        Ok((url, server))
+    }
+
+    // Compiles a .ts output (and the modules it imports) to JavaScript under .lexi/js
+    fn transpile_typescript(&self, output_file: &str) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let output_path = std::path::Path::new(output_file);
+        let source_dir = output_path.parent().unwrap_or(std::path::Path::new(""));
+        let build_dir = source_dir.join(".lexi").join("js");
+        let root_dir = if source_dir.as_os_str().is_empty() { std::path::Path::new(".") } else { source_dir };
+
+        println!("🔨 Transpiling with tsc...");
+        // tsc still emits JavaScript when there are type errors, so only a missing file is fatal
+        process::Command::new("tsc")
+            .args(["--target", "es2020", "--module", "commonjs", "--skipLibCheck", "--outDir"])
+            .arg(&build_dir)
+            .arg("--rootDir")
+            .arg(root_dir)
+            .arg(output_path)
+            .status()?;
+
+        let run_file = build_dir.join(output_path.with_extension("js").file_name().unwrap_or_default());
+        if !run_file.exists() {
+            return Err(format!("tsc did not produce {}", run_file.display()).into());
+        }
+        Ok(run_file)
+    }
+
+    fn run_captured(&self, command: &mut process::Command, output_file: &str) -> Result<process::ExitStatus, Box<dyn std::error::Error>> {
+        use std::io::{Read, Write};
+
+        let output_path = std::path::Path::new(output_file);
+        let log_dir = output_path.parent().unwrap_or(std::path::Path::new(""));
+        let stem = output_path.file_stem().and_then(|s| s.to_str()).unwrap_or("output");
+        let stdout_log = log_dir.join(format!("{}.stdout.log", stem));
+        let stderr_log = log_dir.join(format!("{}.stderr.log", stem));
+
+        let mut child = command
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::piped())
+            .spawn()?;
+
+        // Echo each stream to the terminal while copying it into its log file
+        fn tee<R: Read + Send + 'static, W: Write + Send + 'static>(
+            mut source: R,
+            mut terminal: W,
+            log_path: PathBuf,
+        ) -> std::thread::JoinHandle<std::io::Result<()>> {
+            std::thread::spawn(move || {
+                let mut log = fs::File::create(log_path)?;
+                let mut buffer = [0u8; 8192];
+                loop {
+                    let read = source.read(&mut buffer)?;
+                    if read == 0 {
+                        break;
+                    }
+                    terminal.write_all(&buffer[..read])?;
+                    terminal.flush()?;
+                    log.write_all(&buffer[..read])?;
+                }
+                Ok(())
+            })
+        }
+
+        let stdout_thread = tee(child.stdout.take().unwrap(), std::io::stdout(), stdout_log.clone());
+        let stderr_thread = tee(child.stderr.take().unwrap(), std::io::stderr(), stderr_log.clone());
+
+        let status = child.wait()?;
+        stdout_thread.join().map_err(|_| "stdout capture thread panicked")??;
+        stderr_thread.join().map_err(|_| "stderr capture thread panicked")??;
+
+        println!("📝 Output captured to {} and {}", stdout_log.display(), stderr_log.display());
+        Ok(status)
+    }
+
+    // Ways a target spells a .lxi type; the first is used in prompts. Empty means unchecked.
+    fn type_spellings(&self, target: &str, lexi_type: &str) -> Vec<String> {
+        let Ok(spelled) = self.target(target).map(|target| &target.type_spellings) else {
+            return Vec::new();
+        };
+        let lexi_type = lexi_type.trim();
+        let generic = |name: &str| {
+            lexi_type
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('<'))
+                .and_then(|rest| rest.strip_suffix('>'))
+                .map(|inner| self.split_top_level(inner))
+        };
+
+        if let Some(inner) = generic("list") {
+            let mut spellings = Vec::new();
+            for item in self.type_spellings(target, inner.first().map(String::as_str).unwrap_or("any")) {
+                for template in &spelled.list {
+                    spellings.push(template.replace("{T}", &item));
+                }
+            }
+            return spellings;
+        }
+
+        if let Some(inner) = generic("map") {
+            let values = self.type_spellings(target, inner.get(1).map(String::as_str).unwrap_or("any"));
+            let mut spellings = Vec::new();
+            for key in self.type_spellings(target, inner.first().map(String::as_str).unwrap_or("any")) {
+                for value in &values {
+                    for template in &spelled.map {
+                        spellings.push(template.replace("{K}", &key).replace("{V}", value));
+                    }
+                }
+            }
+            return spellings;
+        }
+
+        if lexi_type == "any" {
+            return Vec::new();
+        }
+        match spelled.builtin.get(lexi_type) {
+            Some(spellings) => spellings.clone(),
+            None => spelled.named.iter().map(|template| template.replace("{T}", lexi_type)).collect(),
+        }
+    }
+
+    fn render_signature(&self, target: &str, signature: &LexiSignature) -> String {
+        let syntax = self.target(target).ok().and_then(|target| target.type_syntax);
+        let spell = |lexi_type: &str| match (syntax, lexi_type.trim()) {
+            // Strict TypeScript has no place for `any`
+            (Some(TypeSyntax::TypeScript), "any") => "unknown".to_string(),
+            _ => self
+                .type_spellings(target, lexi_type)
+                .into_iter()
+                .next()
+                .unwrap_or_else(|| lexi_type.to_string()),
+        };
+        let returns = spell(&signature.returns);
+        let params = |separator: &str, type_first: bool| {
+            signature
+                .params
+                .iter()
+                .map(|(name, lexi_type)| match (type_first, separator) {
+                    (true, _) => format!("{} {}", spell(lexi_type), name),
+                    (false, "") => name.clone(),
+                    (false, _) => format!("{}{}{}", name, separator, spell(lexi_type)),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match syntax {
+            Some(TypeSyntax::Python) => format!("def {}({}) -> {}", signature.name, params(": ", false), returns),
+            Some(TypeSyntax::Rust) if returns.is_empty() => format!("fn {}({})", signature.name, params(": ", false)),
+            Some(TypeSyntax::Rust) => format!("fn {}({}) -> {}", signature.name, params(": ", false), returns),
+            Some(TypeSyntax::Go) => format!("func {}({}) {}", signature.name, params(" ", false), returns).trim_end().to_string(),
+            Some(TypeSyntax::Java) => format!("public static {} {}({})", returns, signature.name, params("", true)),
+            Some(TypeSyntax::Cpp) => format!("{} {}({})", returns, signature.name, params("", true)),
+            Some(TypeSyntax::JavaScript) => format!("function {}({})", signature.name, params("", false)),
+            Some(TypeSyntax::TypeScript) => format!("function {}({}): {}", signature.name, params(": ", false), returns),
+            None => signature.text.clone(),
+        }
+    }
+
+    // Finds the definition of `name`: (return type text, parameters as (name, type) with
+    // an empty type when the target has none)
+    fn find_definition(&self, code: &str, target: &str, name: &str) -> Option<(String, Vec<(String, String)>)> {
+        let target = self.target(target).ok()?;
+        let pattern = target.definition_pattern.as_ref()?.replace("{name}", name);
+        let syntax = target.type_syntax?;
+
+        for (position, _) in code.match_indices(&pattern) {
+            let name_start = position + pattern.len() - name.len() - 1;
+            let line_start = code[..name_start].rfind('\n').map_or(0, |newline| newline + 1);
+            let before = &code[line_start..name_start];
+            if before.ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '.') {
+                continue;
+            }
+
+            // Parameters run to the parenthesis matching the opening one, possibly on later lines
+            let after_paren = &code[position + pattern.len()..];
+            let mut depth = 1;
+            let Some(close) = after_paren.find(|c: char| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                depth == 0
+            }) else {
+                continue;
+            };
+            let params_text = &after_paren[..close];
+            let rest = after_paren[close + 1..].lines().next().unwrap_or_default().trim();
+            let header = |terminator: char| rest.split(terminator).next().unwrap_or_default().trim();
+
+            let returns = match syntax {
+                TypeSyntax::Python => header(':').trim_start_matches("->").trim().to_string(),
+                TypeSyntax::Rust => header('{')
+                    .trim_start_matches("->")
+                    .split(" where")
+                    .next()
+                    .unwrap_or_default()
+                    .trim()
+                    .to_string(),
+                TypeSyntax::Go => header('{').to_string(),
+                TypeSyntax::TypeScript => header('{').trim_start_matches(':').trim().to_string(),
+                TypeSyntax::Java | TypeSyntax::Cpp => {
+                    // A definition has a return type before the name and no semicolon after it; a call
+                    // follows an opening parenthesis, a comma, an operator or a keyword like `return`
+                    let declared = before.trim();
+                    let keywords = ["return", "throw", "new", "else", "case", "delete", "co_return"];
+                    let after_operator = declared.ends_with(|c: char| "(,;{}[=+-/%!|?^~<".contains(c))
+                        || (declared.ends_with(':') && !declared.ends_with("::"))
+                        || declared.ends_with("->")
+                        || declared.ends_with("&&");
+                    let after_keyword = declared.split_whitespace().last().is_some_and(|word| keywords.contains(&word));
+                    if declared.is_empty() || after_operator || after_keyword || rest.starts_with(';') {
+                        continue;
+                    }
+                    let modifiers = ["public", "private", "protected", "static", "final", "inline", "virtual", "synchronized"];
+                    declared
+                        .split_whitespace()
+                        .filter(|word| !modifiers.contains(word))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                }
+                TypeSyntax::JavaScript => String::new(),
+            };
+
+            let mut params: Vec<(String, String)> = self
+                .split_top_level(params_text)
+                .into_iter()
+                .map(|param| {
+                    let param = param.split('=').next().unwrap_or_default().trim().to_string();
+                    match syntax {
+                        TypeSyntax::Python | TypeSyntax::Rust | TypeSyntax::TypeScript => match param.split_once(':') {
+                            Some((param_name, param_type)) => (
+                                param_name.trim().trim_start_matches("mut ").to_string(),
+                                param_type.trim().to_string(),
+                            ),
+                            None => (param, String::new()),
+                        },
+                        TypeSyntax::Go => match param.split_once(' ') {
+                            Some((param_name, param_type)) => (param_name.to_string(), param_type.trim().to_string()),
+                            None => (param, String::new()),
+                        },
+                        TypeSyntax::Java | TypeSyntax::Cpp => match param.rsplit_once(|c: char| c.is_whitespace() || c == '&' || c == '*') {
+                            Some((param_type, param_name)) => {
+                                let suffix = &param[param_type.len()..param.len() - param_name.len()];
+                                (param_name.to_string(), format!("{}{}", param_type.trim(), suffix.trim()))
+                            }
+                            None => (param, String::new()),
+                        },
+                        TypeSyntax::JavaScript => (param, String::new()),
+                    }
+                })
+                .collect();
+
+            // Go lets consecutive parameters share a type: `a, b int`
+            if syntax == TypeSyntax::Go {
+                for index in (0..params.len().saturating_sub(1)).rev() {
+                    if params[index].1.is_empty() {
+                        params[index].1 = params[index + 1].1.clone();
+                    }
+                }
+            }
+
+            return Some((returns, params));
+        }
+
+        None
+    }
+
+    fn verify_signatures(&self, code: &str, target: &str, signatures: &[&LexiSignature]) -> Vec<String> {
+        let normalize = |text: &str| text.chars().filter(|c| !c.is_whitespace()).collect::<String>();
+        let type_matches = |found: &str, lexi_type: &str| {
+            let spellings = self.type_spellings(target, lexi_type);
+            spellings.is_empty() || spellings.iter().any(|spelling| normalize(spelling) == normalize(found))
+        };
+        let expected = |lexi_type: &str| {
+            let spellings = self.type_spellings(target, lexi_type);
+            match spellings.first() {
+                Some(spelling) if !spelling.is_empty() => format!("{} (`{}`)", lexi_type, spelling),
+                _ => lexi_type.to_string(),
+            }
+        };
+
+        let mut errors = Vec::new();
+        for signature in signatures {
+            let Some((returns, params)) = self.find_definition(code, target, &signature.name) else {
+                errors.push(format!("{}: not defined in the generated code", signature.name));
+                continue;
+            };
+
+            let expected_names: Vec<&str> = signature.params.iter().map(|(name, _)| name.as_str()).collect();
+            let found_names: Vec<&str> = params.iter().map(|(name, _)| name.as_str()).collect();
+            if expected_names != found_names {
+                errors.push(format!(
+                    "{}: expected parameters ({}) but found ({})",
+                    signature.name,
+                    expected_names.join(", "),
+                    found_names.join(", ")
+                ));
+                continue;
+            }
+
+            for ((name, lexi_type), (_, found_type)) in signature.params.iter().zip(&params) {
+                if !type_matches(found_type, lexi_type) {
+                    errors.push(format!(
+                        "{}: parameter {} is `{}`, expected {}",
+                        signature.name,
+                        name,
+                        found_type,
+                        expected(lexi_type)
+                    ));
+                }
+            }
+            if !type_matches(&returns, &signature.returns) {
+                errors.push(format!(
+                    "{}: returns `{}`, expected {}",
+                    signature.name,
+                    returns,
+                    expected(&signature.returns)
+                ));
+            }
+        }
+
+        errors
+    }
+
+    fn build_compile_feedback(&self, errors: &[String]) -> String {
+        let mut feedback = String::from("A previous attempt failed to compile. Fix these errors.\n\nErrors:\n");
+        for error in errors {
+            feedback.push_str(&format!("- {}\n", error));
+        }
+        feedback
+    }
+
+    fn build_regex_feedback(&self, failures: &[String]) -> String {
+        let mut feedback = String::from("A previous attempt failed some of the test cases above. Fix the patterns so every case holds.\n\nFailures:\n");
+        for failure in failures {
+            feedback.push_str(&format!("- {}\n", failure));
+        }
+        feedback
+    }
+
+    fn build_data_model_feedback(&self, violations: &[String]) -> String {
+        let mut feedback = String::from(
+            "A previous attempt referenced data that is not declared. Use only the declared collections, fields and keys.\n\nProblems:\n",
+        );
+        for violation in violations {
+            feedback.push_str(&format!("- {}\n", violation));
+        }
+        feedback
+    }
+
+    fn build_style_feedback(&self, violations: &[String]) -> String {
+        let mut feedback = String::from("A previous attempt broke the project's style rules. Follow the style guide.\n\nProblems:\n");
+        for violation in violations {
+            feedback.push_str(&format!("- {}\n", violation));
+        }
+        feedback
+    }
+
+    fn build_dangerous_feedback(&self, found: &[String]) -> String {
+        let mut feedback = String::from(
+            "A previous attempt contained destructive commands. Remove them, and guard any deletion so it can never reach /, ~ or an empty path.\n\nProblems:\n",
+        );
+        for line in found {
+            feedback.push_str(&format!("- {}\n", line));
+        }
+        feedback
+    }
+
+    fn build_signature_feedback(&self, errors: &[String]) -> String {
+        let mut feedback = String::from(
+            "A previous attempt did not follow the required signatures. Use them exactly as given.\n\nProblems:\n",
+        );
+        for error in errors {
+            feedback.push_str(&format!("- {}\n", error));
+        }
+        feedback
+    }
+
+    // How a declared field type is spelled in a struct or class of the target
+    fn field_type(&self, target: &str, lexi_type: &str) -> String {
+        let syntax = self.target(target).ok().and_then(|target| target.type_syntax);
+        let lexi_type = lexi_type.trim();
+        let generic = |name: &str| {
+            lexi_type
+                .strip_prefix(name)
+                .and_then(|rest| rest.strip_prefix('<'))
+                .and_then(|rest| rest.strip_suffix('>'))
+                .map(|inner| self.split_top_level(inner))
+        };
+        // Java collections hold boxed types
+        let element = |inner: Option<&String>| {
+            let spelled = self.field_type(target, inner.map(String::as_str).unwrap_or("any"));
+            match (syntax, spelled.as_str()) {
+                (Some(TypeSyntax::Java), "int") => "Integer".to_string(),
+                (Some(TypeSyntax::Java), "double") => "Double".to_string(),
+                (Some(TypeSyntax::Java), "boolean") => "Boolean".to_string(),
+                _ => spelled,
+            }
+        };
+
+        if let Some(inner) = generic("list") {
+            let item = element(inner.first());
+            return match syntax {
+                Some(TypeSyntax::Python) => format!("list[{}]", item),
+                Some(TypeSyntax::TypeScript) => format!("{}[]", item),
+                Some(TypeSyntax::Rust) => format!("Vec<{}>", item),
+                Some(TypeSyntax::Go) => format!("[]{}", item),
+                Some(TypeSyntax::Java) => format!("java.util.List<{}>", item),
+                Some(TypeSyntax::Cpp) => format!("std::vector<{}>", item),
+                _ => format!("Array<{}>", item),
+            };
+        }
+        if let Some(inner) = generic("map") {
+            let (key, value) = (element(inner.first()), element(inner.get(1)));
+            return match syntax {
+                Some(TypeSyntax::Python) => format!("dict[{}, {}]", key, value),
+                Some(TypeSyntax::TypeScript) => format!("Record<{}, {}>", key, value),
+                Some(TypeSyntax::Rust) => format!("std::collections::HashMap<{}, {}>", key, value),
+                Some(TypeSyntax::Go) => format!("map[{}]{}", key, value),
+                Some(TypeSyntax::Java) => format!("java.util.Map<{}, {}>", key, value),
+                Some(TypeSyntax::Cpp) => format!("std::map<{}, {}>", key, value),
+                _ => format!("Object<{}, {}>", key, value),
+            };
+        }
+
+        let spelled = match (syntax, lexi_type) {
+            (Some(TypeSyntax::Python), "any") => "Any",
+            (Some(TypeSyntax::TypeScript), "any") => "unknown",
+            (Some(TypeSyntax::JavaScript), "any") => "*",
+            (Some(TypeSyntax::Rust), "any") => "AnyValue",
+            (Some(TypeSyntax::Go), "any") => "interface{}",
+            (Some(TypeSyntax::Java), "any") => "Object",
+            (Some(TypeSyntax::Cpp), "any") => "std::any",
+            (Some(TypeSyntax::Rust), "string") => "String",
+            (Some(TypeSyntax::JavaScript), "string") => "string",
+            (Some(TypeSyntax::JavaScript), "int" | "float") => "number",
+            (Some(TypeSyntax::JavaScript), "bool") => "boolean",
+            _ => {
+                return self
+                    .type_spellings(target, lexi_type)
+                    .into_iter()
+                    .next()
+                    .unwrap_or_else(|| lexi_type.to_string())
+            }
+        };
+        spelled.to_string()
+    }
+
+    fn render_types(&self, target: &str, types: &[LexiType]) -> Option<String> {
+        let pascal_case = |name: &str| {
+            name.split('_')
+                .map(|part| {
+                    let mut chars = part.chars();
+                    chars.next().map(|first| first.to_uppercase().chain(chars).collect::<String>()).unwrap_or_default()
+                })
+                .collect::<String>()
+        };
+
+        let syntax = self.target(target).ok()?.type_syntax?;
+        let mut rendered = Vec::new();
+        match syntax {
+            TypeSyntax::Python => rendered.push("from dataclasses import dataclass\nfrom typing import Any, Optional".to_string()),
+            TypeSyntax::Cpp => rendered.push("#include <any>\n#include <map>\n#include <optional>\n#include <string>\n#include <vector>".to_string()),
+            TypeSyntax::TypeScript | TypeSyntax::JavaScript | TypeSyntax::Rust | TypeSyntax::Go | TypeSyntax::Java => {}
+        }
+        // Rust has no built-in type that holds any value, and a crate like serde_json is not available
+        // when `lexi test` builds the file on its own with rustc
+        let mut fields = types.iter().flat_map(|declared| &declared.fields);
+        if syntax == TypeSyntax::Rust && fields.any(|field| self.field_type(target, &field.lexi_type).contains("AnyValue")) {
+            rendered.push(
+                "#[derive(Debug, Clone, PartialEq)]\npub enum AnyValue {\n    Null,\n    Bool(bool),\n    Int(i64),\n    Float(f64),\n    String(String),\n    List(Vec<AnyValue>),\n    Map(std::collections::HashMap<String, AnyValue>),\n}"
+                    .to_string(),
+            );
+        }
+
+        for declared in types {
+            let mut lines = Vec::new();
+            match syntax {
+                TypeSyntax::Python => {
+                    lines.push(format!("@dataclass\nclass {}:", declared.name));
+                    // Dataclass fields with defaults must come after those without
+                    let mut fields: Vec<&LexiField> = declared.fields.iter().collect();
+                    fields.sort_by_key(|field| field.optional);
+                    for field in fields {
+                        let spelled = self.field_type(target, &field.lexi_type);
+                        lines.push(match field.optional {
+                            true => format!("    {}: Optional[{}] = None", field.name, spelled),
+                            false => format!("    {}: {}", field.name, spelled),
+                        });
+                    }
+                    if declared.fields.is_empty() {
+                        lines.push("    pass".to_string());
+                    }
+                }
+                TypeSyntax::TypeScript => {
+                    lines.push(format!("export interface {} {{", declared.name));
+                    for field in &declared.fields {
+                        let optional = if field.optional { "?" } else { "" };
+                        lines.push(format!("  {}{}: {};", field.name, optional, self.field_type(target, &field.lexi_type)));
+                    }
+                    lines.push("}".to_string());
+                }
+                TypeSyntax::JavaScript => {
+                    lines.push(format!("/**\n * @typedef {{Object}} {}", declared.name));
+                    for field in &declared.fields {
+                        let spelled = self.field_type(target, &field.lexi_type);
+                        lines.push(match field.optional {
+                            true => format!(" * @property {{{}}} [{}]", spelled, field.name),
+                            false => format!(" * @property {{{}}} {}", spelled, field.name),
+                        });
+                    }
+                    lines.push(" */".to_string());
+                }
+                TypeSyntax::Rust => {
+                    lines.push(format!("#[derive(Debug, Clone, PartialEq)]\npub struct {} {{", declared.name));
+                    for field in &declared.fields {
+                        let spelled = self.field_type(target, &field.lexi_type);
+                        lines.push(match field.optional {
+                            true => format!("    pub {}: Option<{}>,", field.name, spelled),
+                            false => format!("    pub {}: {},", field.name, spelled),
+                        });
+                    }
+                    lines.push("}".to_string());
+                }
+                TypeSyntax::Go => {
+                    lines.push(format!("type {} struct {{", declared.name));
+                    for field in &declared.fields {
+                        let spelled = self.field_type(target, &field.lexi_type);
+                        lines.push(match field.optional {
+                            true => format!("\t{} *{} `json:\"{},omitempty\"`", pascal_case(&field.name), spelled, field.name),
+                            false => format!("\t{} {} `json:\"{}\"`", pascal_case(&field.name), spelled, field.name),
+                        });
+                    }
+                    lines.push("}".to_string());
+                }
+                TypeSyntax::Java => {
+                    lines.push(format!("class {} {{", declared.name));
+                    for field in &declared.fields {
+                        let mut spelled = self.field_type(target, &field.lexi_type);
+                        // Optional fields need a type that can hold null
+                        if field.optional {
+                            spelled = match spelled.as_str() {
+                                "int" => "Integer".to_string(),
+                                "double" => "Double".to_string(),
+                                "boolean" => "Boolean".to_string(),
+                                _ => spelled,
+                            };
+                        }
+                        lines.push(format!("    public {} {};", spelled, field.name));
+                    }
+                    lines.push("}".to_string());
+                }
+                TypeSyntax::Cpp => {
+                    lines.push(format!("struct {} {{", declared.name));
+                    for field in &declared.fields {
+                        let spelled = self.field_type(target, &field.lexi_type);
+                        lines.push(match field.optional {
+                            true => format!("    std::optional<{}> {};", spelled, field.name),
+                            false => format!("    {} {};", spelled, field.name),
+                        });
+                    }
+                    lines.push("};".to_string());
+                }
+            }
+            rendered.push(lines.join("\n"));
+        }
+
+        Some(rendered.join("\n\n"))
+    }
+
+    fn attach_type_definitions(&self, code: &str, target: &str, definitions: Option<&str>) -> String {
+        let Some(definitions) = definitions else {
+            return code.to_string();
+        };
+
+        match self.target(target).ok().and_then(|target| target.type_syntax) {
+            // Java allows one public class per file, so the package-private types go last
+            Some(TypeSyntax::Java) => format!("{}\n\n{}\n", code.trim_end(), definitions),
+            // Go declarations must follow the package clause and imports
+            Some(TypeSyntax::Go) => {
+                let lines: Vec<&str> = code.lines().collect();
+                let mut insert_at = 0;
+                let mut in_import_group = false;
+                for (index, line) in lines.iter().enumerate() {
+                    let trimmed = line.trim();
+                    if in_import_group {
+                        if trimmed == ")" {
+                            in_import_group = false;
+                            insert_at = index + 1;
+                        }
+                    } else if trimmed.starts_with("package ") || trimmed.starts_with("import ") {
+                        in_import_group = trimmed == "import (";
+                        insert_at = index + 1;
+                    } else if !trimmed.is_empty() && !trimmed.starts_with("//") {
+                        break;
+                    }
+                }
+                format!(
+                    "{}\n\n{}\n\n{}",
+                    lines[..insert_at].join("\n"),
+                    definitions,
+                    lines[insert_at..].join("\n").trim_start()
+                )
+            }
+            _ => format!("{}\n\n{}", definitions, code),
+        }
+    }
+
+    // Whether a program can be found on PATH
+    fn command_available(&self, program: &str) -> bool {
+        let program = std::path::Path::new(program);
+        if program.components().count() > 1 {
+            return program.is_file();
+        }
+        std::env::var_os("PATH").is_some_and(|paths| {
+            std::env::split_paths(&paths).any(|dir| {
+                dir.join(program).is_file() || (cfg!(windows) && dir.join(program).with_extension("exe").is_file())
+            })
+        })
+    }
+
+    fn validate_code(
+        &self,
+        code: &str,
+        target: &str,
+        validator: &str,
+        work_dir: &std::path::Path,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        // Checked next to the output so imported modules resolve
+        let check_path = work_dir.join(format!(".lexi-check-{}{}", process::id(), self.target(target)?.extension));
+        fs::write(&check_path, code)?;
+        let output = self.expand_command(validator, &check_path).and_then(|steps| {
+            let mut output = None;
+            for step in steps {
+                let step_output = process::Command::new(&step[0]).args(&step[1..]).output()?;
+                let failed = !step_output.status.success();
+                output = Some(step_output);
+                if failed {
+                    break;
+                }
+            }
+            output.ok_or_else(|| "empty validator command".into())
+        });
+        let _ = fs::remove_file(&check_path);
+        let output = output?;
+        if output.status.success() {
+            return Ok(Vec::new());
+        }
+
+        // Errors in the checked file look like `<path>(line,column): error ...` or `<path>:line:column: error ...`
+        let check_name = check_path.to_string_lossy();
+        let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        let located = text.lines().any(|line| line.starts_with(check_name.as_ref()));
+        let mut errors: Vec<String> = text
+            .lines()
+            .filter(|line| if located { line.starts_with(check_name.as_ref()) } else { line.contains("error") })
+            .map(|line| {
+                let Some(rest) = line.strip_prefix(check_name.as_ref()) else {
+                    return line.trim().to_string();
+                };
+                let located = match rest.strip_prefix('(') {
+                    Some(rest) => rest.split_once("):").map(|(location, message)| (location.split(',').next(), message)),
+                    None => rest
+                        .trim_start_matches(':')
+                        .split_once(':')
+                        .filter(|(line_number, _)| line_number.chars().all(|c| c.is_ascii_digit()))
+                        .map(|(line_number, message)| (Some(line_number), message.trim_start_matches(|c: char| c.is_ascii_digit() || c == ':'))),
+                };
+                match located {
+                    Some((Some(line_number), message)) => format!("line {}: {}", line_number, message.trim()),
+                    _ => rest.trim_start_matches(':').trim().to_string(),
+                }
+            })
+            .collect();
+        if errors.is_empty() {
+            errors.push(text.trim().to_string());
+        }
+        Ok(errors)
+    }
+
+    // Well-formedness and required keys of configuration
+    fn check_structure(&self, code: &str, format: ConfigFormat) -> Vec<String> {
+        match format {
+            ConfigFormat::Dockerfile => self.check_dockerfile(code),
+            ConfigFormat::Compose => self.check_compose(code),
+            ConfigFormat::Kubernetes => self.check_kubernetes(code),
+            ConfigFormat::Terraform => self.check_terraform(code),
+        }
+    }
+
+    fn check_dockerfile(&self, code: &str) -> Vec<String> {
+        const INSTRUCTIONS: [&str; 18] = [
+            "FROM", "RUN", "CMD", "LABEL", "MAINTAINER", "EXPOSE", "ENV", "ADD", "COPY", "ENTRYPOINT", "VOLUME", "USER", "WORKDIR", "ARG",
+            "ONBUILD", "STOPSIGNAL", "HEALTHCHECK", "SHELL",
+        ];
+        // A heredoc starts after whitespace, unlike a shift such as `$((1<<2))`, and its delimiter is a word
+        static HEREDOC: std::sync::LazyLock<regex::Regex> =
+            std::sync::LazyLock::new(|| regex::Regex::new(r#"(?:^|\s)<<-?["']?([A-Za-z_]\w*)["']?"#).expect("valid heredoc pattern"));
+
+        let mut errors = Vec::new();
+        let mut seen_from = false;
+        let mut continued = false;
+        // Delimiters of the heredocs still open, in the order their bodies follow
+        let mut heredoc_ends: Vec<String> = Vec::new();
+        for (index, line) in code.lines().enumerate() {
+            let trimmed = line.trim();
+            if let Some(end) = heredoc_ends.first() {
+                if trimmed == end {
+                    heredoc_ends.remove(0);
+                }
+                continue;
+            }
+            let is_continuation = continued;
+            continued = trimmed.ends_with('\\');
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                continue;
+            }
+            heredoc_ends.extend(HEREDOC.captures_iter(trimmed).map(|captures| captures[1].to_string()));
+            if is_continuation {
+                continue;
+            }
+
+            let instruction = trimmed.split_whitespace().next().unwrap_or_default().to_uppercase();
+            if !INSTRUCTIONS.contains(&instruction.as_str()) {
+                errors.push(format!("line {}: unknown instruction `{}`", index + 1, instruction));
+            } else if instruction == "FROM" {
+                seen_from = true;
+            } else if !seen_from && instruction != "ARG" {
+                errors.push(format!("line {}: `{}` before the first FROM; only ARG may come before it", index + 1, instruction));
+            }
+        }
+        if !seen_from {
+            errors.push("no FROM instruction".to_string());
+        }
+        errors
+    }
+
+    fn yaml_error(&self, error: &serde_yaml::Error) -> String {
+        match error.location() {
+            Some(location) => format!("line {}: {}", location.line(), error),
+            None => error.to_string(),
+        }
+    }
+
+    fn check_compose(&self, code: &str) -> Vec<String> {
+        let document: serde_yaml::Value = match serde_yaml::from_str(code) {
+            Ok(document) => document,
+            Err(error) => return vec![self.yaml_error(&error)],
+        };
+
+        let Some(services) = document.get("services").and_then(|services| services.as_mapping()).filter(|services| !services.is_empty()) else {
+            return vec!["missing a top-level `services` map".to_string()];
+        };
+        let mut errors = Vec::new();
+        if document.get("version").is_some() {
+            errors.push("the top-level `version` key is obsolete; remove it".to_string());
+        }
+        for (name, service) in services {
+            let name = name.as_str().unwrap_or_default();
+            if !service.is_mapping() {
+                errors.push(format!("service `{}` is not a map", name));
+            } else if service.get("image").is_none() && service.get("build").is_none() {
+                errors.push(format!("service `{}` has neither `image` nor `build`", name));
+            }
+        }
+        errors
+    }
+
+    fn check_kubernetes(&self, code: &str) -> Vec<String> {
+        use serde::Deserialize;
+
+        let mut errors = Vec::new();
+        let mut objects = 0;
+        for (index, document) in serde_yaml::Deserializer::from_str(code).enumerate() {
+            let object = match serde_yaml::Value::deserialize(document) {
+                Ok(serde_yaml::Value::Null) => continue,
+                Ok(object) => object,
+                Err(error) => {
+                    errors.push(format!("document {}: {}", index + 1, self.yaml_error(&error)));
+                    break;
+                }
+            };
+            objects += 1;
+
+            let field = |path: &[&str]| path.iter().try_fold(&object, |value, key| value.get(*key));
+            let text = |path: &[&str]| field(path).and_then(|value| value.as_str()).filter(|value| !value.is_empty());
+            let kind = text(&["kind"]).unwrap_or_default();
+            let label = format!("document {} ({}/{})", index + 1, kind, text(&["metadata", "name"]).unwrap_or("?"));
+            for (path, name) in [(&["apiVersion"][..], "apiVersion"), (&["kind"][..], "kind"), (&["metadata", "name"][..], "metadata.name")] {
+                if text(path).is_none() {
+                    errors.push(format!("{}: missing {}", label, name));
+                }
+            }
+
+            // Workloads need a selector and containers with a name and image
+            let pod_spec: Option<&[&str]> = match kind {
+                "Pod" => Some(&["spec"]),
+                "Deployment" | "StatefulSet" | "DaemonSet" | "ReplicaSet" | "Job" => Some(&["spec", "template", "spec"]),
+                "CronJob" => Some(&["spec", "jobTemplate", "spec", "template", "spec"]),
+                _ => None,
+            };
+            if matches!(kind, "Deployment" | "StatefulSet" | "DaemonSet" | "ReplicaSet") && field(&["spec", "selector"]).is_none() {
+                errors.push(format!("{}: missing spec.selector", label));
+            }
+            if let Some(pod_spec) = pod_spec {
+                let containers = field(pod_spec).and_then(|spec| spec.get("containers")).and_then(|containers| containers.as_sequence());
+                match containers {
+                    Some(containers) if !containers.is_empty() => {
+                        for (position, container) in containers.iter().enumerate() {
+                            for key in ["name", "image"] {
+                                if container.get(key).and_then(|value| value.as_str()).is_none() {
+                                    errors.push(format!("{}: container {} has no {}", label, position + 1, key));
+                                }
+                            }
+                        }
+                    }
+                    _ => errors.push(format!("{}: no containers in {}.containers", label, pod_spec.join("."))),
+                }
+            }
+        }
+        if objects == 0 && errors.is_empty() {
+            errors.push("no Kubernetes objects".to_string());
+        }
+        errors
+    }
+
+    fn check_terraform(&self, code: &str) -> Vec<String> {
+        let body = match hcl::parse(code) {
+            Ok(body) => body,
+            Err(hcl::Error::Parse(error)) => return vec![format!("line {}: {}", error.location().line(), error.message())],
+            Err(error) => return vec![error.to_string()],
+        };
+
+        let mut errors = Vec::new();
+        for block in body.blocks() {
+            let kind = block.identifier();
+            let labels = match kind {
+                "resource" | "data" => Some(2),
+                "variable" | "output" | "module" | "provider" => Some(1),
+                _ => None,
+            };
+            if labels.is_some_and(|labels| block.labels.len() != labels) {
+                errors.push(format!("`{}` block needs {} label(s), found {}", kind, labels.unwrap_or_default(), block.labels.len()));
+            }
+            let required = match kind {
+                "output" => Some("value"),
+                "module" => Some("source"),
+                _ => None,
+            };
+            if let Some(required) = required {
+                if !block.body.attributes().any(|attribute| attribute.key.as_str() == required) {
+                    errors.push(format!("`{}` block has no `{}`", kind, required));
+                }
+            }
+        }
+        errors
+    }
+
+    // The `name = pattern` lines of a regex target's output; a line without a name gets one from its position
+    fn regex_patterns(&self, code: &str) -> Vec<(String, String)> {
+        code.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .enumerate()
+            .map(|(index, line)| match line.split_once(" = ") {
+                Some((name, pattern)) if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') => {
+                    (name.to_string(), pattern.trim().to_string())
+                }
+                _ => (format!("pattern_{}", index + 1), line.to_string()),
+            })
+            .collect()
+    }
+
+    // Paragraphs that describe a pattern, which are those with cases; the others only give context
+    fn pattern_blocks<'b>(&self, blocks: &'b [LexiBlock]) -> Vec<&'b LexiBlock> {
+        blocks.iter().filter(|block| !block.regex_cases.is_empty()).collect()
+    }
+
+    // Compiles each pattern with Rust's regex crate and runs its paragraph's cases as searches, then again in
+    // the flavor's own engine when it is installed
+    fn check_regex(&self, code: &str, blocks: &[LexiBlock], flavor: RegexFlavor) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let blocks = self.pattern_blocks(blocks);
+        let patterns = self.regex_patterns(code);
+        if patterns.len() != blocks.len() {
+            return Ok(vec![format!(
+                "expected {} `name = pattern` line(s), one per paragraph with cases, but found {}",
+                blocks.len(),
+                patterns.len()
+            )]);
+        }
+
+        let mut failures = Vec::new();
+        for ((name, pattern), block) in patterns.iter().zip(&blocks) {
+            let unported = self.regex_portability(pattern, flavor);
+            if !unported.is_empty() {
+                failures.extend(unported.into_iter().map(|problem| format!("`{}` {}", name, problem)));
+                continue;
+            }
+            // JavaScript's \d, \w and \b only know ASCII
+            let checked = match flavor {
+                RegexFlavor::JavaScript => self.ascii_classes(pattern),
+                _ => pattern.clone(),
+            };
+            let regex = match regex::Regex::new(&checked) {
+                Ok(regex) => regex,
+                Err(error) => {
+                    let reason = error.to_string();
+                    failures.push(format!("`{}` is not a valid pattern: {}", name, reason.lines().last().unwrap_or_default().trim_start_matches("error: ")));
+                    continue;
+                }
+            };
+            for case in &block.regex_cases {
+                failures.extend(self.regex_case_failure(name, case, regex.is_match(&case.text), ""));
+            }
+        }
+
+        if failures.is_empty() {
+            failures = self.check_regex_engine(&patterns, &blocks, flavor)?;
+        }
+        Ok(failures)
+    }
+
+    fn regex_case_failure(&self, name: &str, case: &RegexCase, matched: bool, engine: &str) -> Option<String> {
+        let expectation = if case.should_match { "should match" } else { "should not match" };
+        let outcome = if matched { "does" } else { "does not" };
+        (matched != case.should_match)
+            .then(|| format!("line {}: `{}` {} {:?} but {}{}", case.line, name, expectation, case.text, outcome, engine))
+    }
+
+    // Runs the cases against the rendered patterns in node or python3; nothing is reported when the engine
+    // is not installed or the flavor has none
+    fn check_regex_engine(
+        &self,
+        patterns: &[(String, String)],
+        blocks: &[&LexiBlock],
+        flavor: RegexFlavor,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        use std::io::{Read, Write};
+
+        let (program, script, engine) = match flavor {
+            RegexFlavor::JavaScript => (
+                "node",
+                ["-e", "const input = JSON.parse(require('fs').readFileSync(0, 'utf8'));
+console.log(JSON.stringify(input.map(({ source, flags, cases }) => {
+  try {
+    const regex = new RegExp(source, flags);
+    return { matches: cases.map((text) => regex.test(text)) };
+  } catch (error) {
+    return { error: error.message };
+  }
+})));"],
+                "JavaScript",
+            ),
+            RegexFlavor::Python => (
+                "python3",
+                ["-c", "import json, re, sys
+results = []
+for item in json.load(sys.stdin):
+    try:
+        regex = re.compile(item['source'])
+        results.append({'matches': [regex.search(text) is not None for text in item['cases']]})
+    except re.error as error:
+        results.append({'error': str(error)})
+print(json.dumps(results))"],
+                "Python",
+            ),
+            RegexFlavor::Rust | RegexFlavor::Pcre => return Ok(Vec::new()),
+        };
+        if !self.command_available(program) {
+            return Ok(Vec::new());
+        }
+
+        let input: Vec<serde_json::Value> = patterns
+            .iter()
+            .zip(blocks)
+            .map(|((_, pattern), block)| {
+                let (source, flags) = match flavor {
+                    RegexFlavor::JavaScript => self.javascript_pattern(pattern),
+                    _ => (self.python_pattern(pattern), String::new()),
+                };
+                let cases: Vec<&str> = block.regex_cases.iter().map(|case| case.text.as_str()).collect();
+                serde_json::json!({ "source": source, "flags": flags, "cases": cases })
+            })
+            .collect();
+        let mut child = process::Command::new(program)
+            .args(script)
+            .stdin(process::Stdio::piped())
+            .stdout(process::Stdio::piped())
+            .stderr(process::Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(serde_json::to_string(&input)?.as_bytes())?;
+        }
+
+        // Patterns that are linear in Rust's engine can backtrack without end in others
+        let started = std::time::Instant::now();
+        while child.try_wait()?.is_none() {
+            if started.elapsed() > REGEX_ENGINE_TIMEOUT {
+                child.kill()?;
+                child.wait()?;
+                return Ok(vec![format!(
+                    "the cases ran for over {} seconds in {}; a pattern backtracks too much there",
+                    REGEX_ENGINE_TIMEOUT.as_secs(),
+                    engine
+                )]);
+            }
+            std::thread::sleep(std::time::Duration::from_millis(20));
+        }
+        let mut output = String::new();
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout.read_to_string(&mut output)?;
+        }
+        let results: Vec<serde_json::Value> =
+            serde_json::from_str(output.trim()).map_err(|error| format!("Could not read the {} results from {}: {}", engine, program, error))?;
+
+        let mut failures = Vec::new();
+        for (((name, _), block), result) in patterns.iter().zip(blocks).zip(&results) {
+            if let Some(error) = result["error"].as_str() {
+                failures.push(format!("`{}` is not a valid {} pattern: {}", name, engine, error));
+                continue;
+            }
+            let matches = result["matches"].as_array().cloned().unwrap_or_default();
+            for (case, matched) in block.regex_cases.iter().zip(matches) {
+                let matched = matched.as_bool().unwrap_or_default();
+                failures.extend(self.regex_case_failure(name, case, matched, &format!(" in {}", engine)));
+            }
+        }
+        Ok(failures)
+    }
+
+    // Splits a pattern into tokens, each an escape with the character after it or a single character, along
+    // with whether the token is inside a character class
+    fn regex_tokens<'p>(&self, pattern: &'p str) -> Vec<(&'p str, bool)> {
+        let mut tokens = Vec::new();
+        let mut depth = 0;
+        // At the start of a class, where `]` is a literal
+        let mut class_start = false;
+        let mut chars = pattern.char_indices();
+        while let Some((start, c)) = chars.next() {
+            let mut end = start + c.len_utf8();
+            if c == '\\' {
+                end += chars.next().map_or(0, |(_, escaped)| escaped.len_utf8());
+            }
+            let token = &pattern[start..end];
+            let (in_class, first) = (depth > 0, class_start);
+            class_start = false;
+            match token {
+                "[" => {
+                    depth += 1;
+                    class_start = true;
+                }
+                "^" if first => class_start = true,
+                "]" if in_class && !first => depth -= 1,
+                _ => {}
+            }
+            tokens.push((token, in_class));
+        }
+        tokens
+    }
+
+    // Inline flag groups such as `(?i)` or `(?m-s:...)`: (at the start of the pattern, flags, scoped)
+    fn regex_flag_groups(&self, tokens: &[(&str, bool)]) -> Vec<(bool, String, bool)> {
+        let mut groups = Vec::new();
+        for (index, pair) in tokens.windows(2).enumerate() {
+            if pair[0] != ("(", false) || pair[1].0 != "?" {
+                continue;
+            }
+            let flags: String = tokens[index + 2..]
+                .iter()
+                .map(|(token, _)| *token)
+                .take_while(|token| *token == "-" || (token.len() == 1 && token.chars().all(|c| c.is_ascii_alphabetic())))
+                .collect();
+            match tokens.get(index + 2 + flags.len()).map(|(token, _)| *token) {
+                Some(")") if !flags.is_empty() => groups.push((index == 0, flags, false)),
+                Some(":") if !flags.is_empty() => groups.push((index == 0, flags, true)),
+                _ => {}
+            }
+        }
+        groups
+    }
+
+    // Syntax Rust's engine accepts that the flavor lacks, as `uses ...` reasons
+    fn regex_portability(&self, pattern: &str, flavor: RegexFlavor) -> Vec<String> {
+        let flavor_name = match flavor {
+            RegexFlavor::Rust => return Vec::new(),
+            RegexFlavor::Pcre => "PCRE",
+            RegexFlavor::JavaScript => "JavaScript",
+            RegexFlavor::Python => "Python",
+        };
+        let tokens = self.regex_tokens(pattern);
+        let mut problems = Vec::new();
+
+        for (at_start, flags, scoped) in self.regex_flag_groups(&tokens) {
+            let ported = match flavor {
+                // RegExp flags apply to the whole pattern, so only a leading group can become them
+                RegexFlavor::JavaScript => at_start && !scoped && flags.chars().all(|c| "ims".contains(c)),
+                RegexFlavor::Python => flags.chars().all(|c| "imsx-".contains(c)) && (scoped || (at_start && !flags.contains('-'))),
+                _ => flags.chars().all(|c| "imsxU-".contains(c)),
+            };
+            if !ported {
+                let group = if scoped { format!("(?{}:...)", flags) } else { format!("(?{})", flags) };
+                problems.push(format!("uses `{}`, which {} does not support there", group, flavor_name));
+            }
+        }
+
+        let has = |token: &str| tokens.iter().any(|(found, _)| *found == token);
+        let followed = |first: &str, second: &str, in_class: bool| {
+            tokens.windows(2).any(|pair| pair[0] == (first, in_class) && pair[1].0 == second)
+        };
+        let constructs = [
+            ("`\\A`", has("\\A"), flavor == RegexFlavor::JavaScript),
+            ("`\\z`", has("\\z"), flavor != RegexFlavor::Pcre),
+            ("`\\x{...}`", followed("\\x", "{", false) || followed("\\x", "{", true), flavor != RegexFlavor::Pcre),
+            ("Unicode classes like `\\p{L}`", has("\\p") || has("\\P"), flavor == RegexFlavor::Python),
+            ("POSIX classes like `[[:alpha:]]`", followed("[", ":", true), flavor != RegexFlavor::Pcre),
+            (
+                "class set operations (`&&`, `--`, `~~`)",
+                ["&", "-", "~"].iter().any(|op| tokens.windows(2).any(|pair| pair[0] == (*op, true) && pair[1] == (*op, true))),
+                true,
+            ),
+        ];
+        for (construct, used, unsupported) in constructs {
+            if used && unsupported {
+                problems.push(format!("uses {}, which {} does not support", construct, flavor_name));
+            }
+        }
+        problems
+    }
+
+    // The pattern with the ASCII-only \d, \w and \b of JavaScript, for checking with Rust's engine
+    fn ascii_classes(&self, pattern: &str) -> String {
+        self.regex_tokens(pattern)
+            .into_iter()
+            .map(|(token, in_class)| match (token, in_class) {
+                ("\\d", false) => "[0-9]",
+                ("\\d", true) => "0-9",
+                ("\\w", false) => "[0-9A-Za-z_]",
+                ("\\w", true) => "0-9A-Za-z_",
+                ("\\D", false) => "[^0-9]",
+                ("\\W", false) => "[^0-9A-Za-z_]",
+                ("\\b", false) => "(?-u:\\b)",
+                ("\\B", false) => "(?-u:\\B)",
+                (token, _) => token,
+            })
+            .collect()
+    }
+
+    // A JavaScript RegExp source and flags: a leading flag group becomes the flags and `u` is added for
+    // Unicode classes
+    fn javascript_pattern(&self, pattern: &str) -> (String, String) {
+        static LEADING_FLAGS: std::sync::LazyLock<regex::Regex> =
+            std::sync::LazyLock::new(|| regex::Regex::new(r"^\(\?([ims]+)\)").expect("valid flag pattern"));
+
+        let mut flags = LEADING_FLAGS.captures(pattern).map(|captures| captures[1].to_string()).unwrap_or_default();
+        let source = LEADING_FLAGS.replace(pattern, "").replace("(?P<", "(?<");
+        if source.contains("\\p{") || source.contains("\\P{") {
+            flags.push('u');
+        }
+        (source, flags)
+    }
+
+    // A pattern for Python's re: `(?P<name>` groups, and `\Z` for `$` so it doesn't match before a final newline
+    fn python_pattern(&self, pattern: &str) -> String {
+        static NAMED_GROUP: std::sync::LazyLock<regex::Regex> =
+            std::sync::LazyLock::new(|| regex::Regex::new(r"\(\?<([A-Za-z_])").expect("valid named group pattern"));
+
+        let tokens = self.regex_tokens(pattern);
+        let multiline = self.regex_flag_groups(&tokens).iter().any(|(_, flags, _)| flags.split('-').next().unwrap_or_default().contains('m'));
+        let pattern: String = tokens
+            .into_iter()
+            .map(|(token, in_class)| match (token, in_class) {
+                ("$", false) if !multiline => "\\Z",
+                (token, _) => token,
+            })
+            .collect();
+        NAMED_GROUP.replace_all(&pattern, "(?P<$1").to_string()
+    }
+
+    // Writes the checked patterns in the target's flavor, each under its paragraph's first line
+    fn render_regex(&self, code: &str, blocks: &[LexiBlock], target: &str, flavor: RegexFlavor) -> String {
+        // Escapes `delimiter` wherever the pattern has not already escaped it
+        let escape = |pattern: &str, delimiter: char| {
+            let mut escaped = String::new();
+            let mut backslash = false;
+            for c in pattern.chars() {
+                if c == delimiter && !backslash {
+                    escaped.push('\\');
+                }
+                backslash = c == '\\' && !backslash;
+                escaped.push(c);
+            }
+            escaped
+        };
+
+        let blocks = self.pattern_blocks(blocks);
+        let mut lines = Vec::new();
+        if flavor == RegexFlavor::Python {
+            lines.push("import re".to_string());
+            lines.push(String::new());
+        }
+        for (index, (name, pattern)) in self.regex_patterns(code).iter().enumerate() {
+            if let Some(description) = blocks.get(index).and_then(|block| block.text.lines().next()) {
+                lines.push(format!("{} {}", self.comment_prefix(target), description));
+            }
+            lines.push(match flavor {
+                RegexFlavor::JavaScript => {
+                    let (source, flags) = self.javascript_pattern(pattern);
+                    let mut words = name.split('_').filter(|word| !word.is_empty());
+                    let first = words.next().unwrap_or_default().to_lowercase();
+                    let rest: String = words
+                        .map(|word| {
+                            let mut chars = word.chars();
+                            chars.next().map(|c| c.to_uppercase().collect::<String>()).unwrap_or_default() + &chars.as_str().to_lowercase()
+                        })
+                        .collect();
+                    format!("export const {}{} = /{}/{};", first, rest, escape(&source, '/'), flags)
+                }
+                RegexFlavor::Python => format!("{} = re.compile(r\"{}\")", name.to_uppercase(), escape(&self.python_pattern(pattern), '"')),
+                RegexFlavor::Rust | RegexFlavor::Pcre => format!("{} = {}", name, pattern),
+            });
+            lines.push(String::new());
+        }
+        lines.join("\n").trim_end().to_string()
+    }
+
+    // Runs `terraform validate` on the configuration alone, in .lexi/terraform next to the output
+    fn validate_terraform(&self, code: &str, work_dir: &std::path::Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let dir = work_dir.join(".lexi").join("terraform");
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("main.tf"), code)?;
+
+        // Providers are fetched once and reused; without them only provider-independent errors are reported.
+        // Modules the model chose are never downloaded, so their contents go unchecked
+        let initialized = process::Command::new("terraform")
+            .arg(format!("-chdir={}", dir.display()))
+            .args(["init", "-backend=false", "-get=false", "-input=false", "-no-color"])
+            .output()?
+            .status
+            .success();
+        let output = process::Command::new("terraform")
+            .arg(format!("-chdir={}", dir.display()))
+            .args(["validate", "-json", "-no-color"])
+            .output()?;
+
+        let report: serde_json::Value = serde_json::from_slice(&output.stdout)?;
+        let diagnostics = report["diagnostics"].as_array().cloned().unwrap_or_default();
+        Ok(diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic["severity"] == "error")
+            .filter(|diagnostic| initialized || diagnostic["summary"] != "Missing required provider")
+            .filter(|diagnostic| diagnostic["summary"] != "Module not installed")
+            .map(|diagnostic| {
+                let message = format!("{}: {}", diagnostic["summary"].as_str().unwrap_or_default(), diagnostic["detail"].as_str().unwrap_or_default());
+                match diagnostic["range"]["start"]["line"].as_u64() {
+                    Some(line) => format!("line {}: {}", line, message.trim_end_matches(": ")),
+                    None => message.trim_end_matches(": ").to_string(),
+                }
+            })
+            .collect())
+    }
+
+    // The command a build check runs with, or None when its tool is not installed. Compose prefers the v2
+    // plugin, since `docker` may be installed without it
+    fn build_check_program(&self, check: BuildCheck) -> Option<&'static str> {
+        match check {
+            BuildCheck::Compose => {
+                let plugin = self.command_available("docker")
+                    && process::Command::new("docker")
+                        .args(["compose", "version"])
+                        .stdout(process::Stdio::null())
+                        .stderr(process::Stdio::null())
+                        .status()
+                        .is_ok_and(|status| status.success());
+                match plugin {
+                    true => Some("docker compose"),
+                    false => self.command_available("docker-compose").then_some("docker-compose"),
+                }
+            }
+            check => self.command_available(check.program()).then_some(check.program()),
+        }
+    }
+
+    // Builds the program as the only source of a temporary project, turning file-based app directives
+    // (`#:package`, `#:property`, `#:sdk`) into project settings
+    fn validate_dotnet(&self, code: &str, work_dir: &std::path::Path) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let dir = work_dir.join(".lexi").join("dotnet");
+        fs::create_dir_all(&dir)?;
+
+        let mut sdk = "Microsoft.NET.Sdk".to_string();
+        let mut properties = vec![
+            "<OutputType>Exe</OutputType>".to_string(),
+            "<TargetFramework>net10.0</TargetFramework>".to_string(),
+            "<ImplicitUsings>enable</ImplicitUsings>".to_string(),
+            "<Nullable>enable</Nullable>".to_string(),
+        ];
+        let mut packages = Vec::new();
+        // Directive lines are blanked so errors keep their line numbers
+        let mut source = Vec::new();
+        for line in code.lines() {
+            let directive = line.trim().strip_prefix("#:");
+            match directive.and_then(|directive| directive.split_once(char::is_whitespace)) {
+                Some(("package", package)) => {
+                    let (name, version) = package.trim().split_once('@').unwrap_or((package.trim(), "*"));
+                    packages.push(format!("<PackageReference Include=\"{}\" Version=\"{}\" />", name, version));
+                }
+                Some(("property", property)) => {
+                    if let Some((name, value)) = property.trim().split_once('=') {
+                        properties.push(format!("<{0}>{1}</{0}>", name.trim(), value.trim()));
+                    }
+                }
+                Some(("sdk", name)) => sdk = name.trim().split('@').next().unwrap_or_default().to_string(),
+                _ => {}
+            }
+            source.push(if directive.is_some() || line.starts_with("#!") { "" } else { line });
+        }
+
+        let project = format!(
+            "<Project Sdk=\"{}\">\n  <PropertyGroup>\n    {}\n  </PropertyGroup>\n  <ItemGroup>\n    {}\n  </ItemGroup>\n</Project>\n",
+            sdk,
+            properties.join("\n    "),
+            packages.join("\n    ")
+        );
+        fs::write(dir.join("check.csproj"), project)?;
+        fs::write(dir.join("Program.cs"), source.join("\n"))?;
+
+        let output = process::Command::new("dotnet")
+            .args(["build", "--nologo", "-v", "quiet", "-clp:NoSummary"])
+            .arg(dir.join("check.csproj"))
+            .output()?;
+        if output.status.success() {
+            return Ok(Vec::new());
+        }
+
+        // Errors look like `<dir>/Program.cs(3,5): error CS0103: ... [<dir>/check.csproj]`, once per target
+        let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        let mut errors = Vec::new();
+        for line in text.lines().filter(|line| line.contains(": error ")) {
+            let line = line.rsplit_once(" [").map_or(line, |(message, _)| message).trim();
+            let error = match line.split_once("Program.cs(").and_then(|(_, rest)| rest.split_once("): ")) {
+                Some((location, message)) => format!("line {}: {}", location.split(',').next().unwrap_or_default(), message),
+                None => line.to_string(),
+            };
+            if !errors.contains(&error) {
+                errors.push(error);
+            }
+        }
+        if errors.is_empty() {
+            errors.push(text.trim().to_string());
+        }
+        Ok(errors)
+    }
+
+    // Runs the statements against an in-memory database holding the schema
+    fn validate_sqlite(&self, code: &str, schema: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let connection = rusqlite::Connection::open_in_memory()?;
+        connection
+            .execute_batch(schema)
+            .map_err(|error| format!("The schema does not load in SQLite: {}", error))?;
+
+        let mut errors = Vec::new();
+        let mut batch = rusqlite::Batch::new(&connection, code);
+        loop {
+            match batch.next() {
+                Ok(Some(mut statement)) => {
+                    // Queries and RETURNING clauses are stepped through their rows like any other statement
+                    let mut rows = statement.raw_query();
+                    let stepped = loop {
+                        match rows.next() {
+                            Ok(Some(_)) => {}
+                            Ok(None) => break Ok(()),
+                            Err(error) => break Err(error),
+                        }
+                    };
+                    drop(rows);
+                    if let Err(error) = stepped {
+                        let text = statement.expanded_sql().unwrap_or_default();
+                        errors.push(format!("{} (in `{}`)", error, text.split_whitespace().collect::<Vec<_>>().join(" ")));
+                    }
+                }
+                Ok(None) => break,
+                // A statement that fails to prepare ends the batch
+                Err(error) => {
+                    errors.push(error.to_string());
+                    break;
+                }
+            }
+        }
+        Ok(errors)
+    }
+
+    fn check_examples<'a>(
+        &self,
+        code: &str,
+        target: &str,
+        blocks: &'a [LexiBlock],
+        work_dir: &std::path::Path,
+    ) -> Result<Vec<ExampleFailure<'a>>, Box<dyn std::error::Error>> {
+        let cases: Vec<(&LexiBlock, &LexiExample)> = blocks
+            .iter()
+            .flat_map(|block| block.examples.iter().map(move |example| (block, example)))
+            .collect();
+        let examples: Vec<&LexiExample> = cases.iter().map(|(_, example)| *example).collect();
+
+        let (program, extension, harness) = match self.target(target)?.example_runner {
+            Some(ExampleRunner::Node) => ("node", "js", self.build_javascript_harness(code, &examples)),
+            Some(ExampleRunner::Python) => ("python", "py", self.build_python_harness(code, &examples)),
+            None => return Err(format!("Example checks not supported for {}", target).into()),
+        };
+
+        // The harness sits next to the output so imported modules resolve; its output goes
+        // to temp files so the run can be killed on timeout
+        let harness_path = work_dir.join(format!(".lexi-examples-{}.{}", process::id(), extension));
+        let harness_dir = std::env::temp_dir().join(format!("lexi-examples-{}", process::id()));
+        let _scratch = ScratchPaths(vec![harness_path.clone(), harness_dir.clone()]);
+        fs::create_dir_all(&harness_dir)?;
+        let stdout_path = harness_dir.join("stdout.txt");
+        let stderr_path = harness_dir.join("stderr.txt");
+        fs::write(&harness_path, harness)?;
+
+        let mut child = process::Command::new(program)
+            .arg(&harness_path)
+            .stdin(process::Stdio::null())
+            .stdout(fs::File::create(&stdout_path)?)
+            .stderr(fs::File::create(&stderr_path)?)
+            .spawn()?;
+
+        let started = std::time::Instant::now();
+        let mut timed_out = false;
+        while child.try_wait()?.is_none() {
+            if started.elapsed() > EXAMPLE_TIMEOUT {
+                child.kill()?;
+                child.wait()?;
+                timed_out = true;
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+
+        let stdout = fs::read_to_string(&stdout_path).unwrap_or_default();
+        let stderr = fs::read_to_string(&stderr_path).unwrap_or_default();
+
+        // Harness lines look like: LEXI_EXAMPLE<TAB>PASS|FAIL<TAB>index[<TAB>detail]
+        let mut results: HashMap<usize, Option<String>> = HashMap::new();
+        for line in stdout.lines() {
+            let parts: Vec<&str> = line.splitn(4, '\t').collect();
+            if parts.len() < 3 || parts[0] != "LEXI_EXAMPLE" {
+                continue;
+            }
+            if let Ok(index) = parts[2].parse::<usize>() {
+                let detail = (parts[1] != "PASS").then(|| parts.get(3).unwrap_or(&"failed").to_string());
+                results.insert(index, detail);
+            }
+        }
+
+        let not_run_reason = if timed_out {
+            format!("timed out after {}s", EXAMPLE_TIMEOUT.as_secs())
+        } else {
+            let last_error = stderr.lines().rev().find(|line| !line.trim().is_empty()).unwrap_or("no output");
+            format!("not run: {}", last_error.trim())
+        };
+
+        let failures = cases
+            .into_iter()
+            .enumerate()
+            .filter_map(|(index, (block, example))| {
+                let detail = match results.get(&index) {
+                    Some(None) => return None,
+                    Some(Some(detail)) => detail.clone(),
+                    None => not_run_reason.clone(),
+                };
+                Some(ExampleFailure { block, example, detail })
+            })
+            .collect();
+
+        Ok(failures)
+    }
+
+    fn build_javascript_harness(&self, code: &str, examples: &[&LexiExample]) -> String {
+        let cases: Vec<String> = examples
+            .iter()
+            .enumerate()
+            .map(|(index, example)| format!("    [{}, () => ({}), () => ({})],", index, example.call, example.expected))
+            .collect();
+
+        // Hide the harness from `require.main === module` so a blocking main or server entry
+        // stays idle; a shebang has to stay on the first line
+        let (shebang, body) = match code.strip_prefix("#!") {
+            Some(_) => code.split_once('\n').map(|(first, rest)| (format!("{}\n", first), rest)).unwrap_or_default(),
+            None => (String::new(), code),
+        };
+
+        format!(
+            r#"{}require.main = undefined;
+{}
+
+;(() => {{
+  const cases = [
+{}
+  ];
+  for (const [index, actual, expected] of cases) {{
+    try {{
+      const got = actual();
+      const want = expected();
+      if (JSON.stringify(got) === JSON.stringify(want)) {{
+        console.log(`LEXI_EXAMPLE	PASS	${{index}}`);
+      }} else {{
+        console.log(`LEXI_EXAMPLE	FAIL	${{index}}	got ${{JSON.stringify(got)}}`);
+      }}
+    }} catch (error) {{
+      console.log(`LEXI_EXAMPLE	FAIL	${{index}}	threw ${{error && error.message}}`);
+    }}
+  }}
+}})();
+"#,
+            shebang,
+            body,
+            cases.join("\n")
+        )
     }
 
-    fn run_output(&self, output_file: &str, target: &str) -> Result<(), Box<dyn std::error::Error>> {
-        match target {
-            "javascript" => {
-                process::Command::new("node")
-                    .arg(output_file)
-                    .status()?;
-            }
-            "python" => {
-                process::Command::new("python")
-                    .arg(output_file)
-                    .status()?;
+    fn build_python_harness(&self, code: &str, examples: &[&LexiExample]) -> String {
+        // Examples are written with JSON-style literals; translate the ones Python spells differently,
+        // leaving string literals untouched
+        let pythonize = |expression: &str| {
+            let mut result = String::new();
+            let mut word = String::new();
+            let mut quote: Option<char> = None;
+            let mut escaped = false;
+            let flush = |word: &mut String, result: &mut String| {
+                result.push_str(match word.as_str() {
+                    "true" => "True",
+                    "false" => "False",
+                    "null" => "None",
+                    other => other,
+                });
+                word.clear();
+            };
+
+            for c in expression.chars() {
+                if let Some(open) = quote {
+                    result.push(c);
+                    if escaped {
+                        escaped = false;
+                    } else if c == '\\' {
+                        escaped = true;
+                    } else if c == open {
+                        quote = None;
+                    }
+                } else if c.is_alphanumeric() || c == '_' {
+                    word.push(c);
+                } else {
+                    flush(&mut word, &mut result);
+                    if c == '"' || c == '\'' {
+                        quote = Some(c);
+                    }
+                    result.push(c);
+                }
             }
-            _ => {
-                println!("⚠️  Auto-run not supported for {} yet", target);
+            flush(&mut word, &mut result);
+            result
+        };
+
+        let cases: Vec<String> = examples
+            .iter()
+            .enumerate()
+            .map(|(index, example)| {
+                format!(
+                    "        ({}, lambda: ({}), lambda: ({})),",
+                    index,
+                    pythonize(&example.call),
+                    pythonize(&example.expected)
+                )
+            })
+            .collect();
+
+        // The code runs as an imported module so an `if __name__ == "__main__"` entry point
+        // (a blocking main loop or server) stays idle while the examples are checked
+        format!(
+            r#"import sys as __lexi_sys
+import types as __lexi_types
+
+__lexi_module = __lexi_types.ModuleType("lexi_generated")
+__lexi_module.__file__ = __file__
+__lexi_sys.modules["lexi_generated"] = __lexi_module
+exec(compile({}, __file__, "exec"), __lexi_module.__dict__)
+globals().update({{name: value for name, value in vars(__lexi_module).items() if not name.startswith("__")}})
+
+
+def __lexi_check_examples():
+    cases = [
+{}
+    ]
+    for index, actual, expected in cases:
+        try:
+            got = actual()
+            want = expected()
+            if got == want:
+                print(f"LEXI_EXAMPLE	PASS	{{index}}")
+            else:
+                print(f"LEXI_EXAMPLE	FAIL	{{index}}	got {{got!r}}")
+        except Exception as error:
+            print(f"LEXI_EXAMPLE	FAIL	{{index}}	threw {{error!r}}")
+
+
+__lexi_check_examples()
+"#,
+            serde_json::to_string(code).unwrap_or_default(),
+            cases.join("\n")
+        )
+    }
+
+    fn report_examples(&self, input: &str, blocks: &[LexiBlock], failures: &[ExampleFailure]) {
+        for block in blocks {
+            for example in &block.examples {
+                match failures.iter().find(|failure| std::ptr::eq(failure.example, example)) {
+                    Some(failure) => println!(
+                        "   ❌ {} -> {} ({}) [{}:{}]",
+                        example.call, example.expected, failure.detail, input, example.line
+                    ),
+                    None => println!("   ✅ {} -> {}", example.call, example.expected),
+                }
             }
         }
-        Ok(())
+
+        let total: usize = blocks.iter().map(|block| block.examples.len()).sum();
+        println!("   {} passed, {} failed", total - failures.len(), failures.len());
+    }
+
+    fn build_example_feedback(&self, failures: &[ExampleFailure]) -> String {
+        let mut feedback = String::from(
+            "A previous attempt failed some of the examples above. Fix the code so every example holds.\n\nFailing examples:\n",
+        );
+
+        for failure in failures {
+            feedback.push_str(&format!(
+                "- {} should be {} but {} (from lines {}-{}: \"{}\")\n",
+                failure.example.call,
+                failure.example.expected,
+                failure.detail,
+                failure.block.start_line,
+                failure.block.end_line,
+                failure.block.text.lines().next().unwrap_or_default()
+            ));
+        }
+
+        feedback
     }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = Cli::parse();
-    let compiler = LexiCompiler::new();
+    let compiler = match LexiCompiler::new() {
+        Ok(compiler) => compiler,
+        Err(error) => {
+            eprintln!("❌ Error: {}", error);
+            process::exit(1);
+        }
+    };
 
     match cli.command {
-        Commands::Compile { input, target, output, run } => {
-            compiler.compile(&input, &target, output.as_deref(), run).await?;
+        Commands::Compile {
+            input,
+            target,
+            profile,
+            model,
+            temperature,
+            max_tokens,
+            schema,
+            allow_dangerous,
+            output,
+            run,
+            capture,
+            no_examples,
+            overwrite,
+            source_map,
+            inline_refs,
+            args,
+        } => {
+            let run_options = run.then_some(RunOptions { args, capture });
+            let options = CompileOptions {
+                check_examples: !no_examples,
+                overwrite,
+                source_map,
+                inline_refs,
+                overrides: ModelOverrides {
+                    profile,
+                    model,
+                    temperature,
+                    max_tokens,
+                },
+                schema,
+                allow_dangerous,
+            };
+            if let Err(error) = compiler.compile(&input, target.as_deref(), output.as_deref(), run_options, &options).await {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        }
+        Commands::Test { files, target, report } => {
+            if let Err(error) = compiler.run_tests(&files, target.as_deref(), report.as_deref()).await {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        }
+        Commands::Watch { target, run, debounce } => {
+            if let Err(error) = compiler.watch(target.as_deref(), run, debounce).await {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        }
+        Commands::Decompile { input, output, target } => {
+            if let Err(error) = compiler.decompile(&input, output.as_deref(), target.as_deref()).await {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
+        }
+        Commands::Refine { file, instruction, target, yes, allow_dangerous } => {
+            if let Err(error) = compiler.refine(&file, &instruction, target.as_deref(), yes, allow_dangerous).await {
+                eprintln!("❌ Error: {}", error);
+                process::exit(1);
+            }
         }
         Commands::Init { project_name } => {
             compiler.init_project(&project_name)?;
@@ -940,7 +6435,995 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 compiler.set_profile_config(&profile, &key, &value)?;
             }
         },
+        Commands::Targets => {
+            compiler.list_targets();
+        }
+        Commands::Prompt { prompt_command } => match prompt_command {
+            PromptCommands::Show { file, target } => {
+                if let Err(error) = compiler.show_prompt(&file, target.as_deref()) {
+                    eprintln!("❌ Error: {}", error);
+                    process::exit(1);
+                }
+            }
+        },
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redis_key(pattern: &str, key_type: &str, fields: &[&str]) -> RedisKey {
+        RedisKey {
+            pattern: pattern.to_string(),
+            key_type: key_type.to_string(),
+            fields: fields.iter().map(|field| field.to_string()).collect(),
+            description: None,
+        }
+    }
+
+    // An empty directory for one test, under the system temp directory
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("lexi-test-{}-{}", process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn compile_options() -> CompileOptions {
+        CompileOptions {
+            check_examples: false,
+            overwrite: false,
+            source_map: false,
+            inline_refs: false,
+            overrides: ModelOverrides::default(),
+            schema: None,
+            allow_dangerous: false,
+        }
+    }
+
+    #[test]
+    fn js_bracket_end_skips_strings_and_comments() {
+        let compiler = LexiCompiler::new().unwrap();
+        let text = r#"{ a: "}", b: '{', c: `)`, /* } */ d: 1 // }
+        } rest"#;
+        assert_eq!(compiler.js_bracket_end(text), text.find("} rest"));
+        let escaped = r#"{ a: "\"}", b: [1, 2] } x"#;
+        assert_eq!(compiler.js_bracket_end(escaped), escaped.rfind('}'));
+        assert_eq!(compiler.js_bracket_end("(f(1), [2]) + 3"), Some(10));
+    }
+
+    #[test]
+    fn js_bracket_end_is_none_when_unclosed() {
+        let compiler = LexiCompiler::new().unwrap();
+        assert_eq!(compiler.js_bracket_end("{ a: { b: 1 }"), None);
+        assert_eq!(compiler.js_bracket_end(r#"{ a: "} "#), None);
+        assert_eq!(compiler.js_bracket_end("{ a: 1 /* } "), None);
+    }
+
+    #[test]
+    fn split_js_top_level_keeps_nested_commas() {
+        let compiler = LexiCompiler::new().unwrap();
+        let parts = compiler.split_js_top_level(r#"a, f(b, c), [d, e], "x,y", { k: 1, l: 2 }, // z,
+        w"#);
+        let parts: Vec<&str> = parts.iter().map(|part| part.trim()).collect();
+        assert_eq!(parts, ["a", "f(b, c)", "[d, e]", r#""x,y""#, "{ k: 1, l: 2 }", "// z,\n        w"]);
+        assert!(compiler.split_js_top_level("  ").is_empty());
+        assert_eq!(compiler.split_js_top_level("a, b,").len(), 2);
+    }
+
+    #[test]
+    fn js_object_entries_reads_keys() {
+        let compiler = LexiCompiler::new().unwrap();
+        let entries = compiler.js_object_entries(r#"{ name: "a:b", "age": { $gt: 2 }, email, ...rest, [key]: 1, 'tags': [1, 2] }"#);
+        let keys: Vec<&str> = entries.iter().map(|(key, _)| key.as_str()).collect();
+        assert_eq!(keys, ["name", "age", "email", "tags"]);
+        assert_eq!(entries[1].1.trim(), "{ $gt: 2 }");
+        assert!(compiler.js_object_entries("[1, 2]").is_empty());
+        assert!(compiler.js_object_entries("{ a: 1").is_empty());
+    }
+
+    #[test]
+    fn check_mongo_reports_undeclared_collections_and_fields() {
+        let compiler = LexiCompiler::new().unwrap();
+        let collections = vec![(
+            "users".to_string(),
+            vec![("name".to_string(), "string".to_string()), ("address.city".to_string(), "string".to_string())],
+        )];
+        let code = r#"
+            db.users.find({ name: "a", $or: [{ "address.city": "x" }, { agee: 3 }] });
+            db.users.updateOne({ name: "a" }, { $set: { nick: "b" } });
+            db.orders.insertOne({ total: 1 });
+        "#;
+        let violations = compiler.check_mongo(code, &collections);
+        assert_eq!(
+            violations,
+            [
+                "collection `orders` is not declared",
+                "users: field `agee` is not declared",
+                "users: field `nick` is not declared",
+            ]
+        );
+    }
+
+    #[test]
+    fn redis_command_type_uses_the_command_table() {
+        let compiler = LexiCompiler::new().unwrap();
+        assert_eq!(compiler.redis_command_type("GET"), Some("string"));
+        assert_eq!(compiler.redis_command_type("LMOVE"), Some("list"));
+        assert_eq!(compiler.redis_command_type("SMEMBERS"), Some("set"));
+        assert_eq!(compiler.redis_command_type("HGETALL"), Some("hash"));
+        assert_eq!(compiler.redis_command_type("ZRANGEBYSCORE"), Some("zset"));
+        assert_eq!(compiler.redis_command_type("XADD"), Some("stream"));
+        // Commands that merely share a prefix with a type's commands work on any key or none
+        for command in ["HELLO", "XREAD", "XGROUP", "ZUNION", "DEL", "EXPIRE", "TYPE"] {
+            assert_eq!(compiler.redis_command_type(command), None, "{}", command);
+        }
+    }
+
+    #[test]
+    fn shell_denylist_catches_recursive_rm_in_any_flag_order() {
+        let compiler = LexiCompiler::new().unwrap();
+        for line in [
+            "rm -rf /",
+            "rm -fr /*",
+            "rm -f -r /",
+            "rm -v --force -R ~",
+            "rm --recursive --force /",
+            "sudo rm -r -- \"$HOME\"",
+            "rm -rf \"$DIR\"/",
+            "rm -rf \"${BUILD_DIR}\"/*",
+            "rm -rf $TMP/ && echo done",
+            "rm -rf \"$DIR/\"",
+        ] {
+            assert_eq!(compiler.find_dangerous(line, "bash").unwrap().len(), 1, "{}", line);
+        }
+        for line in [
+            "rm -f /tmp/lock",
+            "rm -rf \"$DIR\"/build",
+            "rm -rf \"${DIR:?}\"/",
+            "rm -rf ./dist",
+            "# rm -rf /",
+            "rm /",
+        ] {
+            assert!(compiler.find_dangerous(line, "bash").unwrap().is_empty(), "{}", line);
+        }
+    }
+
+    #[test]
+    fn check_dockerfile_skips_heredoc_bodies_only() {
+        let compiler = LexiCompiler::new().unwrap();
+        let dockerfile = "\
+FROM alpine:3.20
+RUN echo $((1<<2)) && \\
+    cat <<EOF > /etc/motd
+hello
+EOF
+COPY <<-'A' <<B /srv/
+a
+A
+b
+B
+RUN echo $((1 <<2))
+BOGUS x
+";
+        assert_eq!(compiler.check_dockerfile(dockerfile), ["line 12: unknown instruction `BOGUS`"]);
+    }
+
+    #[test]
+    fn regex_portability_rejects_what_the_flavor_lacks() {
+        let compiler = LexiCompiler::new().unwrap();
+        let unported = |pattern: &str, flavor: RegexFlavor| compiler.regex_portability(pattern, flavor).len();
+        assert_eq!(unported(r"(?i)^[a-z]+\z", RegexFlavor::Rust), 0);
+        assert_eq!(unported(r"(?i)^[a-z]+$", RegexFlavor::JavaScript), 0);
+        assert_eq!(unported(r"^(?i:ab)c$", RegexFlavor::JavaScript), 1);
+        assert_eq!(unported(r"(?x)^a b$", RegexFlavor::JavaScript), 1);
+        assert_eq!(unported(r"^a(?i)b$", RegexFlavor::JavaScript), 1);
+        assert_eq!(unported(r"(?x)^a b$", RegexFlavor::Python), 0);
+        assert_eq!(unported(r"(?U)^a+\p{L}$", RegexFlavor::Python), 2);
+        assert_eq!(unported(r"(?U)^a+\p{L}$", RegexFlavor::Pcre), 0);
+        assert_eq!(unported(r"^[[:alpha:]]+\z", RegexFlavor::JavaScript), 2);
+        assert_eq!(unported(r"^[a-z&&[^aeiou]]$", RegexFlavor::Pcre), 1);
+        // Escaped and literal look-alikes are not flag groups or classes
+        assert_eq!(unported(r"^\(\?i\)[(?i)]:$", RegexFlavor::JavaScript), 0);
+    }
+
+    #[test]
+    fn regex_flavors_keep_rust_semantics() {
+        let compiler = LexiCompiler::new().unwrap();
+        assert_eq!(compiler.ascii_classes(r"^\d+[\w-]\b\\d$"), r"^[0-9]+[0-9A-Za-z_-](?-u:\b)\\d$");
+        assert_eq!(compiler.python_pattern(r"^(?<zip>\d{5})[$]\$$"), r"^(?P<zip>\d{5})[$]\$\Z");
+        assert_eq!(compiler.python_pattern(r"(?m)^a$"), r"(?m)^a$");
+        assert_eq!(compiler.javascript_pattern(r"(?i)^(?P<w>\p{L}+)$"), (r"^(?<w>\p{L}+)$".to_string(), "iu".to_string()));
+    }
+
+    #[test]
+    fn check_regex_pairs_patterns_with_paragraphs_that_have_cases() {
+        let compiler = LexiCompiler::new().unwrap();
+        let blocks = compiler.parse_blocks(
+            "These patterns validate a signup form.\n\nUS zip codes.\nShould match: 12345\nShould not match: 1234\n\nLowercase words.\nShould match: abc\n",
+        );
+        let code = "zip_code = ^\\d{5}$\nword = ^[a-z]+$";
+        assert!(compiler.check_regex(code, &blocks, RegexFlavor::Rust).unwrap().is_empty());
+        assert_eq!(
+            compiler.check_regex("zip_code = ^\\d{4}$\nword = ^[a-z]+$", &blocks, RegexFlavor::Rust).unwrap(),
+            [r#"line 4: `zip_code` should match "12345" but does not"#, r#"line 5: `zip_code` should not match "1234" but does"#]
+        );
+        let rendered = compiler.render_regex(code, &blocks, "regex", RegexFlavor::Rust);
+        assert_eq!(rendered, "# US zip codes.\nzip_code = ^\\d{5}$\n\n# Lowercase words.\nword = ^[a-z]+$");
+    }
+
+    #[test]
+    fn check_redis_reports_key_type_and_field_mismatches() {
+        let compiler = LexiCompiler::new().unwrap();
+        let keys = [redis_key("user:{id}", "hash", &["name", "email"]), redis_key("queue:{name}", "list", &[])];
+        let code = "\
+# comment
+HSET user:42 name \"Ada Lovelace\" email a@b.c
+redis-cli hget user:42 phone
+LPUSH user:42 x
+RPUSH queue:jobs 1
+SET session:1 x
+EXPIRE queue:jobs 60
+";
+        assert_eq!(
+            compiler.check_redis(code, &keys),
+            [
+                "line 3: `user:{id}` has no declared field `phone`",
+                "line 4: LPUSH works on a list, but `user:{id}` is declared as hash",
+                "line 6: key `session:1` does not match any declared key pattern",
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_sqlite_runs_queries_and_returning_clauses() {
+        let compiler = LexiCompiler::new().unwrap();
+        let schema = "CREATE TABLE users (id INTEGER PRIMARY KEY, name TEXT NOT NULL);";
+        let code = "\
+INSERT INTO users (name) VALUES ('Ada');
+SELECT COUNT(*) FROM users;
+INSERT INTO users (name) VALUES ('Grace') RETURNING id;
+SELECT name FROM users WHERE id = 2;
+";
+        assert!(compiler.validate_sqlite(code, schema).unwrap().is_empty());
+
+        let errors = compiler.validate_sqlite("INSERT INTO users (name) VALUES (NULL) RETURNING id;", schema).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("NOT NULL constraint failed"), "{}", errors[0]);
+
+        let errors = compiler.validate_sqlite("SELECT email FROM users;", schema).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("no such column: email"), "{}", errors[0]);
+    }
+
+    #[test]
+    fn resolve_block_markers_maps_blocks_to_output_lines_and_bytes() {
+        let compiler = LexiCompiler::new().unwrap();
+        let block = |start_line: usize, end_line: usize| LexiBlock {
+            start_line,
+            end_line,
+            text: String::new(),
+            examples: Vec::new(),
+            signatures: Vec::new(),
+            regex_cases: Vec::new(),
+        };
+        let blocks = [block(3, 4), block(7, 9)];
+        let code = "use std::fmt;\n// lexi:block 1\nfn a() {}\n\n// lexi:block 2\nfn b() {\n}\n";
+
+        let (resolved, mappings) = compiler.resolve_block_markers(code, &blocks, "app.lxi", "rust", false);
+        assert_eq!(resolved, "use std::fmt;\nfn a() {}\n\nfn b() {\n}");
+        let spans: Vec<_> = mappings
+            .iter()
+            .map(|mapping| (mapping.block, mapping.source.start_line, mapping.output.start_line, mapping.output.end_line))
+            .collect();
+        assert_eq!(spans, [(1, 3, 2, 2), (2, 7, 4, 5)]);
+        assert_eq!(&resolved[mappings[0].output.start_byte..mappings[0].output.end_byte], "fn a() {}");
+        assert_eq!(&resolved[mappings[1].output.start_byte..mappings[1].output.end_byte], "fn b() {\n}");
+
+        let (resolved, mappings) = compiler.resolve_block_markers(code, &blocks, "app.lxi", "rust", true);
+        assert_eq!(resolved, "use std::fmt;\n// app.lxi:3\nfn a() {}\n\n// app.lxi:7\nfn b() {\n}");
+        assert_eq!((mappings[1].output.start_line, mappings[1].output.end_line), (5, 7));
+        assert_eq!(&resolved[mappings[0].output.start_byte..mappings[0].output.end_byte], "// app.lxi:3\nfn a() {}");
+    }
+
+    #[test]
+    fn fenced_body_drops_prose_and_keeps_inner_fences() {
+        let compiler = LexiCompiler::new().unwrap();
+        let response = "Here is the updated file:\n\n```python\n#!/usr/bin/env python3\nprint(1)\n```\n\nI renamed the function.";
+        assert_eq!(compiler.fenced_body(response).unwrap(), "#!/usr/bin/env python3\nprint(1)");
+
+        let response = "```python\nHELP = \"\"\"\n```\nlexi compile app.lxi\n```\n\"\"\"\n```";
+        assert_eq!(compiler.fenced_body(response).unwrap(), "HELP = \"\"\"\n```\nlexi compile app.lxi\n```\n\"\"\"");
+
+        let response = "````markdown\n# Usage\n```sh\nlexi build\n```\n````\nDone.";
+        assert_eq!(compiler.fenced_body(response).unwrap(), "# Usage\n```sh\nlexi build\n```");
+
+        assert_eq!(compiler.fenced_body("print(1)\n"), None);
+    }
+
+    #[test]
+    fn project_targets_change_built_ins_and_add_custom_ones() {
+        let project = serde_json::from_str(
+            r#"{"targets": {
+                "Python": {"aliases": ["py3"], "validators": ["ruff check {file}"]},
+                "bash": {"denylist": ["curl .*\\| *sh"]},
+                "lua": {"extension": ".lua", "fences": ["lua"], "comment": "--", "runners": ["lua"]},
+                "fennel": {"extension": ".fnl"}
+            }}"#,
+        )
+        .unwrap();
+        let compiler = LexiCompiler::with_project(project).unwrap();
+
+        let python = compiler.target("py3").unwrap();
+        assert_eq!(python.name, "python");
+        assert_eq!(python.aliases, ["py", "py3"]);
+        assert_eq!(python.extension, ".py");
+        assert_eq!(python.validators, ["ruff check {file}"]);
+        assert!(python.type_syntax.is_some() && !python.custom);
+        let bash = compiler.target("bash").unwrap();
+        assert_eq!(bash.denylist.last().map(String::as_str), Some("curl .*\\| *sh"));
+        assert!(bash.denylist.len() > 1);
+
+        let lua = compiler.target("LUA").unwrap();
+        assert!(lua.custom && lua.definition_pattern.is_none());
+        assert_eq!((lua.extension.as_str(), lua.comment.as_str()), (".lua", "--"));
+        assert_eq!(compiler.target("fennel").unwrap().comment, "//");
+        assert_eq!(compiler.target_from_extension("init.lua").as_deref(), Some("lua"));
+
+        let error = compiler.canonical_target("cobol").unwrap_err().to_string();
+        assert_eq!(error, "Unknown target 'cobol'. Run `lexi targets` to see the supported ones");
+    }
+
+    #[test]
+    fn custom_targets_need_an_extension() {
+        let project = serde_json::from_str(r#"{"targets": {"lua": {"comment": "--"}}}"#).unwrap();
+        let error = LexiCompiler::with_project(project).err().unwrap().to_string();
+        assert_eq!(error, "Target 'lua' in lexi.config.json has no extension, e.g. \".lua\"");
+    }
+
+    #[test]
+    fn write_output_merges_hand_edits_and_reports_conflicts() {
+        let compiler = LexiCompiler::new().unwrap();
+        let file = temp_dir("merge").join("app.py");
+        let read = || fs::read_to_string(&file).unwrap();
+
+        assert_eq!(compiler.write_output(&file, "a = 1\nb = 2\nc = 3\nd = 4\ne = 5", false).unwrap(), 0);
+        fs::write(&file, "a = 1\nb = 20\nc = 3\nd = 4\ne = 5\n").unwrap();
+        assert_eq!(compiler.write_output(&file, "a = 1\nb = 2\nc = 3\nd = 4\ne = 6", false).unwrap(), 0);
+        assert_eq!(read(), "a = 1\nb = 20\nc = 3\nd = 4\ne = 6\n");
+
+        fs::write(&file, "a = 1\nb = 20\nc = 3\nd = 4\ne = 7\n").unwrap();
+        assert_eq!(compiler.write_output(&file, "a = 1\nb = 2\nc = 3\nd = 4\ne = 8", false).unwrap(), 1);
+        assert!(read().starts_with("a = 1\nb = 20\nc = 3\nd = 4\n<<<<<<<"), "{}", read());
+
+        assert_eq!(compiler.write_output(&file, "a = 1\nb = 2\nc = 3\nd = 4\ne = 8", true).unwrap(), 0);
+        assert_eq!(read(), "a = 1\nb = 2\nc = 3\nd = 4\ne = 8\n");
+    }
+
+    #[test]
+    fn write_output_backs_up_output_with_no_earlier_generation() {
+        let compiler = LexiCompiler::new().unwrap();
+        let dir = temp_dir("no-base");
+        let file = dir.join("app.py");
+        fs::write(&file, "print('written by hand')\n").unwrap();
+
+        assert_eq!(compiler.write_output(&file, "print('generated')", false).unwrap(), 0);
+        assert_eq!(fs::read_to_string(&file).unwrap(), "print('generated')\n");
+        assert_eq!(fs::read_to_string(dir.join(".lexi/app.py.bak")).unwrap(), "print('written by hand')\n");
+        assert_eq!(fs::read_to_string(dir.join(".lexi/app.py")).unwrap(), "print('generated')\n");
+    }
+
+    #[test]
+    fn restore_keep_regions_keeps_hand_written_regions() {
+        let compiler = LexiCompiler::new().unwrap();
+        let existing = "x = 1\n# lexi:keep begin config\nPORT = 9000\n# lexi:keep end\n# lexi:keep begin extra\nDEBUG = True\n# lexi:keep end\n";
+        let generated = "x = 2\n# lexi:keep begin config\nPORT = 8080\n# lexi:keep end\n";
+        assert_eq!(
+            compiler.restore_keep_regions(existing, generated),
+            "x = 2\n# lexi:keep begin config\nPORT = 9000\n# lexi:keep end\n\n# lexi:keep begin extra\nDEBUG = True\n# lexi:keep end\n"
+        );
+    }
+
+    #[test]
+    fn verify_signatures_checks_python_definitions() {
+        let compiler = LexiCompiler::new().unwrap();
+        let code = "\
+def add(a: int, b: int) -> int:
+    return a + b
+
+
+def scale(
+    values: list[float],
+    factor: float = 1.0,
+) -> list[float]:
+    return [value * factor for value in values]
+";
+        assert_eq!(compiler.find_definition(code, "python", "add"), Some(("int".to_string(), vec![("a".to_string(), "int".to_string()), ("b".to_string(), "int".to_string())])));
+        let verify = |line: &str| compiler.verify_signatures(code, "python", &[&compiler.parse_signature(line).unwrap()]);
+
+        assert!(verify("fn add(a: int, b: int) -> int").is_empty());
+        assert!(verify("fn scale(values: list<float>, factor: float) -> list<float>").is_empty());
+        assert_eq!(verify("fn subtract(a: int, b: int) -> int"), ["subtract: not defined in the generated code"]);
+        assert_eq!(verify("fn add(a: int, b: int, c: int) -> int"), ["add: expected parameters (a, b, c) but found (a, b)"]);
+        assert_eq!(verify("fn add(a: int, b: int) -> string"), ["add: returns `int`, expected string (`str`)"]);
+    }
+
+    #[test]
+    fn verify_signatures_checks_java_definitions_and_skips_calls() {
+        let compiler = LexiCompiler::new().unwrap();
+        let code = "\
+public class Calc {
+    public static void main(String[] args) {
+        System.out.println(add(1, 2));
+    }
+
+    public static int add(int a, int b) {
+        return a + b;
+    }
+}
+";
+        assert_eq!(compiler.find_definition(code, "java", "add"), Some(("int".to_string(), vec![("a".to_string(), "int".to_string()), ("b".to_string(), "int".to_string())])));
+        let verify = |line: &str| compiler.verify_signatures(code, "java", &[&compiler.parse_signature(line).unwrap()]);
+
+        assert!(verify("fn add(a: int, b: int) -> int").is_empty());
+        assert_eq!(verify("fn multiply(a: int, b: int) -> int"), ["multiply: not defined in the generated code"]);
+        assert_eq!(verify("fn add(a: int) -> int"), ["add: expected parameters (a) but found (a, b)"]);
+        assert_eq!(verify("fn add(a: int, b: string) -> int"), ["add: parameter b is `int`, expected string (`String`)"]);
+    }
+
+    #[test]
+    fn resolve_imports_orders_dependencies_and_rejects_cycles() {
+        let compiler = LexiCompiler::new().unwrap();
+        let dir = temp_dir("imports");
+        fs::write(dir.join("main.lxi"), "@import util.lxi\n@import math.lxi\n\nPrint a report.\n").unwrap();
+        fs::write(dir.join("util.lxi"), "@import math.lxi\n\nFormat numbers.\n").unwrap();
+        fs::write(dir.join("math.lxi"), "Add numbers.\n").unwrap();
+        let order: Vec<String> = compiler
+            .resolve_imports(&dir.join("main.lxi"))
+            .unwrap()
+            .iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(order, ["math.lxi", "util.lxi"]);
+
+        fs::write(dir.join("a.lxi"), "@import b.lxi\n").unwrap();
+        fs::write(dir.join("b.lxi"), "@import a.lxi\n").unwrap();
+        let error = compiler.resolve_imports(&dir.join("a.lxi")).unwrap_err().to_string();
+        assert_eq!(error, "Import cycle: a.lxi -> b.lxi -> a.lxi");
+    }
+
+    #[test]
+    fn prepare_job_wires_imported_modules_for_the_target() {
+        let compiler = LexiCompiler::new().unwrap();
+        let dir = temp_dir("wiring");
+        let build = dir.join("build");
+        fs::create_dir_all(&build).unwrap();
+        fs::write(build.join("util.js"), "function pad(text) {}\nmodule.exports = { pad };\n").unwrap();
+        fs::write(build.join("util.py"), "def pad(text):\n    pass\n").unwrap();
+        let options = compile_options();
+        let input = dir.join("main.lxi").to_string_lossy().to_string();
+        let content = "@import util.lxi\n\nPrint a padded report.\n";
+
+        let job = compiler.prepare_job(&input, content, "javascript", &build, false, &options).unwrap();
+        assert!(job.context.contains("module.exports = { pad };"), "{}", job.context);
+        assert!(job.context.contains("Load it with `const { ... } = require('./util.js')`."), "{}", job.context);
+
+        let job = compiler.prepare_job(&input, content, "python", &build, true, &options).unwrap();
+        assert!(job.context.contains("Import it with `from util import ...`."), "{}", job.context);
+        assert!(job.context.contains("library module imported by other files. Put any script code under `if __name__ == \"__main__\":`."));
+
+        let error = compiler.prepare_job(&input, content, "bash", &build, false, &options).err().unwrap().to_string();
+        assert_eq!(error, "@import is not supported for target 'bash'");
+    }
+
+    #[test]
+    fn render_types_renders_each_target() {
+        let compiler = LexiCompiler::new().unwrap();
+        let types = compiler.parse_types("type Order { id: int, tags: list<string>, note?: string, meta: any }").unwrap();
+        let expected = [
+            ("python", r#"from dataclasses import dataclass
+from typing import Any, Optional
+
+@dataclass
+class Order:
+    id: int
+    tags: list[str]
+    meta: Any
+    note: Optional[str] = None"#),
+            ("typescript", r#"export interface Order {
+  id: number;
+  tags: string[];
+  note?: string;
+  meta: unknown;
+}"#),
+            ("javascript", r#"/**
+ * @typedef {Object} Order
+ * @property {number} id
+ * @property {Array<string>} tags
+ * @property {string} [note]
+ * @property {*} meta
+ */"#),
+            ("rust", r#"#[derive(Debug, Clone, PartialEq)]
+pub enum AnyValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    List(Vec<AnyValue>),
+    Map(std::collections::HashMap<String, AnyValue>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: i64,
+    pub tags: Vec<String>,
+    pub note: Option<String>,
+    pub meta: AnyValue,
+}"#),
+            ("go", r#"type Order struct {
+	Id int `json:"id"`
+	Tags []string `json:"tags"`
+	Note *string `json:"note,omitempty"`
+	Meta interface{} `json:"meta"`
+}"#),
+            ("java", r#"class Order {
+    public int id;
+    public java.util.List<String> tags;
+    public String note;
+    public Object meta;
+}"#),
+            ("cpp", r#"#include <any>
+#include <map>
+#include <optional>
+#include <string>
+#include <vector>
+
+struct Order {
+    int id;
+    std::vector<std::string> tags;
+    std::optional<std::string> note;
+    std::any meta;
+};"#),
+        ];
+        for (target, rendered) in expected {
+            assert_eq!(compiler.render_types(target, &types).unwrap(), rendered, "{}", target);
+        }
+        assert_eq!(compiler.render_types("bash", &types), None);
+
+        // The enum for any values is only rendered when a field needs it
+        let types = compiler.parse_types("type Point { x: float, y: float }").unwrap();
+        assert!(!compiler.render_types("rust", &types).unwrap().contains("AnyValue"));
+    }
+
+    #[test]
+    fn render_template_fills_variables_and_drops_empty_lines() {
+        let compiler = LexiCompiler::new().unwrap();
+        let template = "Write {{target}} code.\n\n{{style}}\n\n{{signatures}}\n\nStyle notes: {{style}}\n\n{{ request }}";
+        let variables = [("target", "python"), ("style", ""), ("signatures", ""), ("request", "Add numbers.\n")];
+        assert_eq!(compiler.render_template(template, &variables).unwrap(), "Write python code.\n\nStyle notes: \n\nAdd numbers.");
+
+        let variables = [("target", "python"), ("style", "Use snake_case."), ("signatures", "def add(a: int) -> int"), ("request", "Add.")];
+        assert_eq!(
+            compiler.render_template(template, &variables).unwrap(),
+            "Write python code.\n\nUse snake_case.\n\ndef add(a: int) -> int\n\nStyle notes: Use snake_case.\n\nAdd."
+        );
+
+        let error = compiler.render_template("Write {{target}} for {{platform}}", &variables).unwrap_err().to_string();
+        assert_eq!(error, "Prompt template uses unknown variable {{platform}}");
+        let error = compiler.render_template("Write {{target", &variables).unwrap_err().to_string();
+        assert_eq!(error, "Prompt template has an unclosed {{");
+    }
+
+    #[test]
+    fn find_template_prefers_project_then_home_then_built_in() {
+        let compiler = LexiCompiler::new().unwrap();
+        let root = temp_dir("templates");
+        let (project, home) = (root.join("project"), root.join("home"));
+        fs::create_dir_all(&project).unwrap();
+        fs::create_dir_all(&home).unwrap();
+        let dirs = [project.clone(), home.clone()];
+        let find = |target: &str| compiler.find_template(&dirs, target, "system").unwrap();
+
+        assert_eq!(find("sql:postgres"), ("sql.system.md".to_string(), None));
+        assert_eq!(find("python"), ("default.system.md".to_string(), None));
+
+        fs::write(home.join("sql.system.md"), "home").unwrap();
+        assert_eq!(find("sql:postgres"), ("sql.system.md".to_string(), Some(home.join("sql.system.md"))));
+        fs::write(project.join("sql.system.md"), "project").unwrap();
+        assert_eq!(find("sql:postgres"), ("sql.system.md".to_string(), Some(project.join("sql.system.md"))));
+        // A template for the dialect beats one for its family, wherever it is
+        fs::write(home.join("sql.postgres.system.md"), "home dialect").unwrap();
+        assert_eq!(find("sql:postgres"), ("sql.postgres.system.md".to_string(), Some(home.join("sql.postgres.system.md"))));
+
+        let error = compiler.find_template(&dirs, "python", "review").unwrap_err().to_string();
+        assert_eq!(error, "No review prompt template found for target 'python'");
+    }
+
+    #[test]
+    fn parse_frontmatter_reads_settings_and_keeps_line_numbers() {
+        let compiler = LexiCompiler::new().unwrap();
+        let source = "---\ntarget: python\n# comment\ntemperature: 0.2\n---\nAdd two numbers.\n";
+        let (frontmatter, body) = compiler.parse_frontmatter(source).unwrap();
+        assert_eq!(frontmatter.target.as_deref(), Some("python"));
+        assert_eq!(frontmatter.overrides.temperature, Some(0.2));
+        assert_eq!(body, "\n\n\n\n\nAdd two numbers.");
+        assert_eq!(compiler.parse_blocks(&body)[0].start_line, 6);
+
+        let (_, user_prompt) = compiler.build_prompt(&body, "python", &[], None).unwrap();
+        assert!(user_prompt.starts_with("Convert this Lexi description into python code:\n\nAdd two numbers.\n\n"), "{}", user_prompt);
+
+        let source = "Add two numbers.\n---\n";
+        let (frontmatter, body) = compiler.parse_frontmatter(source).unwrap();
+        assert!(frontmatter.target.is_none() && frontmatter.style.is_none());
+        assert_eq!(body, source);
+
+        let error = compiler.parse_frontmatter("---\ntarget: python\nAdd two numbers.\n").err().unwrap().to_string();
+        assert_eq!(error, "Frontmatter line 3: expected `key: value`");
+        let error = compiler.parse_frontmatter("---\ntarget: python\n").err().unwrap().to_string();
+        assert_eq!(error, "Frontmatter is missing its closing ---");
+        let error = compiler.parse_frontmatter("---\ncolour: blue\n---\n").err().unwrap().to_string();
+        assert_eq!(error, "Frontmatter line 2: unknown key 'colour'");
+        let error = compiler.parse_frontmatter("---\nmax_tokens: many\n---\n").err().unwrap().to_string();
+        assert_eq!(error, "Frontmatter line 2: max_tokens must be a whole number");
+    }
+
+    #[test]
+    fn write_generated_refuses_notebooks_with_edited_cells() {
+        let compiler = LexiCompiler::new().unwrap();
+        let dir = temp_dir("notebook");
+        let file = dir.join("stats.ipynb");
+        let input = dir.join("stats.lxi").to_string_lossy().to_string();
+        let content = "Compute the mean of a list.\n\nPrint the mean of 1, 2 and 3.\n";
+        let job = compiler.prepare_job(&input, content, "jupyter", &dir, false, &compile_options()).unwrap();
+        let code = "import statistics\n# lexi:block 1\ndef mean(values):\n    return statistics.mean(values)\n# lexi:block 2\nprint(mean([1, 2, 3]))\n";
+
+        compiler.write_generated(&file, code, &job, false).unwrap();
+        assert!(!compiler.notebook_edited(&file));
+
+        // Outputs from running the notebook are not edits, so it is regenerated
+        let mut notebook: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        notebook["cells"][4]["outputs"] = serde_json::json!([{ "output_type": "stream", "name": "stdout", "text": ["2\n"] }]);
+        notebook["cells"][4]["execution_count"] = serde_json::json!(1);
+        fs::write(&file, notebook.to_string()).unwrap();
+        assert!(!compiler.notebook_edited(&file));
+        compiler.write_generated(&file, &code.replace("[1, 2, 3]", "[1, 2, 4]"), &job, false).unwrap();
+        assert!(fs::read_to_string(&file).unwrap().contains("print(mean([1, 2, 4]))"));
+
+        let mut notebook: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        notebook["cells"][4]["source"] = serde_json::json!(["print(mean([10, 20]))"]);
+        fs::write(&file, notebook.to_string()).unwrap();
+        assert!(compiler.notebook_edited(&file));
+        let error = compiler.write_generated(&file, code, &job, false).unwrap_err().to_string();
+        assert!(error.ends_with("has cells edited since it was generated, and notebooks are not merged. Pass --overwrite to replace it"));
+        assert!(fs::read_to_string(&file).unwrap().contains("print(mean([10, 20]))"));
+
+        compiler.write_generated(&file, code, &job, true).unwrap();
+        assert!(fs::read_to_string(&file).unwrap().contains("print(mean([1, 2, 3]))"));
+        assert!(!compiler.notebook_edited(&file));
+    }
+
+    #[test]
+    fn web_bundle_is_linked_and_split_into_files() {
+        let compiler = LexiCompiler::new().unwrap();
+        let response = "\
+Here is the page:
+
+```html
+<html>
+<head><title>Counter</title></head>
+<body><button>0</button></body>
+</html>
+```
+
+```css
+button { font-size: 2em; }
+```
+
+```js
+document.querySelector('button').onclick = (event) => event.target.textContent++;
+```
+Open index.html to try it.";
+        let bundle = compiler.extract_web_bundle(response, "web");
+        let files = compiler.web_files(&bundle);
+        let names: Vec<&str> = files.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["index.html", "style.css", "app.js"]);
+        assert_eq!(
+            files[0].1,
+            "<html>\n<head><title>Counter</title>  <link rel=\"stylesheet\" href=\"style.css\">\n</head>\n<body><button>0</button>  <script src=\"app.js\" defer></script>\n</body>\n</html>"
+        );
+        assert_eq!(files[1].1, "button { font-size: 2em; }");
+
+        let dir = temp_dir("web");
+        let input = dir.join("counter.lxi").to_string_lossy().to_string();
+        let job = compiler.prepare_job(&input, "A counter button.\n", "web", &dir, false, &compile_options()).unwrap();
+        compiler.write_generated(&dir.join("counter").join("index.html"), &bundle, &job, false).unwrap();
+        assert!(fs::read_to_string(dir.join("counter/index.html")).unwrap().contains("<button>0</button>"));
+        assert_eq!(fs::read_to_string(dir.join("counter/style.css")).unwrap(), "button { font-size: 2em; }\n");
+        assert!(dir.join("counter/app.js").exists());
+    }
+
+    #[test]
+    fn serve_static_only_serves_visible_files_under_the_root() {
+        use std::io::{Read, Write};
+
+        let compiler = LexiCompiler::new().unwrap();
+        let dir = temp_dir("serve");
+        let root = dir.join("site");
+        fs::create_dir_all(root.join(".lexi")).unwrap();
+        fs::write(root.join("index.html"), "<h1>Home</h1>").unwrap();
+        fs::write(root.join("app.js"), "console.log(1);").unwrap();
+        fs::write(root.join(".lexi/index.html"), "base").unwrap();
+        fs::write(dir.join("secret.txt"), "secret").unwrap();
+
+        let (url, _server) = compiler.serve_static(&root).unwrap();
+        let address = url.trim_start_matches("http://").trim_end_matches('/').replace("localhost", "127.0.0.1");
+        let request = |method: &str, path: &str| {
+            let mut stream = std::net::TcpStream::connect(&address).unwrap();
+            write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\n\r\n", method, path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let home = request("GET", "/");
+        assert!(home.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/html") && home.ends_with("<h1>Home</h1>"), "{}", home);
+        assert!(request("GET", "/app.js?v=2").contains("Content-Type: text/javascript"));
+        for path in ["/../secret.txt", "/..", "/.lexi/index.html", "/.lexi/", "/missing.js"] {
+            assert!(request("GET", path).starts_with("HTTP/1.1 404 Not Found"), "{}", path);
+        }
+        assert!(request("POST", "/").starts_with("HTTP/1.1 405 Method Not Allowed"));
+    }
+
+    fn example(call: &str, expected: &str) -> LexiExample {
+        LexiExample {
+            call: call.to_string(),
+            expected: expected.to_string(),
+            line: 1,
+        }
+    }
+
+    // Runs a harness with `program` when it is installed, returning its stdout and exit code
+    fn run_harness(compiler: &LexiCompiler, program: &str, file: PathBuf, harness: &str) -> Option<(String, Option<i32>)> {
+        if !compiler.command_available(program) {
+            return None;
+        }
+        fs::write(&file, harness).unwrap();
+        let output = process::Command::new(program).arg(&file).output().unwrap();
+        Some((String::from_utf8_lossy(&output.stdout).to_string(), output.status.code()))
+    }
+
+    #[test]
+    fn python_harness_checks_examples_without_running_main() {
+        let compiler = LexiCompiler::new().unwrap();
+        let code = "\
+import sys
+
+
+def add(a, b):
+    return a + b
+
+
+def is_empty(items):
+    return len(items) == 0
+
+
+if __name__ == \"__main__\":
+    print(\"main ran\")
+    sys.exit(3)
+";
+        let examples = [
+            example("add(1, 2)", "3"),
+            example("is_empty([])", "true"),
+            example("add(\"null\", \"\")", "\"null\""),
+            example("add(2, 2)", "5"),
+            example("is_empty(null)", "false"),
+        ];
+        let harness = compiler.build_python_harness(code, &examples.iter().collect::<Vec<_>>());
+        assert!(harness.contains("        (1, lambda: (is_empty([])), lambda: (True)),\n"));
+        assert!(harness.contains("        (2, lambda: (add(\"null\", \"\")), lambda: (\"null\")),\n"));
+        assert!(harness.contains("        (4, lambda: (is_empty(None)), lambda: (False)),\n"));
+        assert!(harness.contains(&format!("exec(compile({}, __file__, \"exec\")", serde_json::to_string(code).unwrap())));
+
+        if let Some((stdout, status)) = run_harness(&compiler, "python3", temp_dir("python-harness").join("check.py"), &harness) {
+            let lines: Vec<&str> = stdout.lines().collect();
+            assert_eq!(status, Some(0), "{}", stdout);
+            assert_eq!(lines[..4], ["LEXI_EXAMPLE\tPASS\t0", "LEXI_EXAMPLE\tPASS\t1", "LEXI_EXAMPLE\tPASS\t2", "LEXI_EXAMPLE\tFAIL\t3\tgot 4"]);
+            assert!(lines[4].starts_with("LEXI_EXAMPLE\tFAIL\t4\tthrew TypeError("), "{}", lines[4]);
+            assert_eq!(lines.len(), 5);
+        }
+    }
+
+    #[test]
+    fn javascript_harness_checks_examples_without_running_main() {
+        let compiler = LexiCompiler::new().unwrap();
+        let code = "\
+#!/usr/bin/env node
+function add(a, b) { return a + b; }
+if (require.main === module) { console.log('main ran'); process.exit(3); }
+module.exports = { add };";
+        let examples = [example("add(1, 2)", "3"), example("add([1], [2])", "\"12\""), example("add(2, 2)", "5"), example("missing(1)", "1")];
+        let harness = compiler.build_javascript_harness(code, &examples.iter().collect::<Vec<_>>());
+        assert!(harness.starts_with("#!/usr/bin/env node\nrequire.main = undefined;\nfunction add(a, b)"));
+        assert!(harness.contains("    [1, () => (add([1], [2])), () => (\"12\")],\n"));
+
+        if let Some((stdout, status)) = run_harness(&compiler, "node", temp_dir("javascript-harness").join("check.js"), &harness) {
+            assert_eq!(status, Some(0), "{}", stdout);
+            assert_eq!(
+                stdout.lines().collect::<Vec<_>>(),
+                [
+                    "LEXI_EXAMPLE\tPASS\t0",
+                    "LEXI_EXAMPLE\tPASS\t1",
+                    "LEXI_EXAMPLE\tFAIL\t2\tgot 4",
+                    "LEXI_EXAMPLE\tFAIL\t3\tthrew missing is not defined",
+                ]
+            );
+        }
+    }
+
+    fn test_cases(compiler: &LexiCompiler, runner: TestRunner, output: &str) -> Vec<(String, bool)> {
+        let framework = compiler.test_framework(runner, "math", "math.x").unwrap();
+        compiler.parse_test_cases(output, framework.case_pattern).unwrap()
+    }
+
+    fn case(name: &str, passed: bool) -> (String, bool) {
+        (name.to_string(), passed)
+    }
+
+    #[test]
+    fn parses_test_cases_from_each_runner() {
+        let compiler = LexiCompiler::new().unwrap();
+
+        let pytest = "\
+..F                                                                      [100%]
+=========================== short test summary info ============================
+PASSED test_math.py::test_add
+PASSED test_math.py::test_subtract[1-2]
+FAILED test_math.py::test_divide - ZeroDivisionError: division by zero
+1 failed, 2 passed in 0.03s
+";
+        assert_eq!(
+            test_cases(&compiler, TestRunner::Pytest, pytest),
+            [case("test_add", true), case("test_subtract[1-2]", true), case("test_divide", false)]
+        );
+
+        let junit = "\
+╷
+├─ JUnit Jupiter ✔
+│  └─ MathTest ✔
+│     ├─ addsNumbers() ✔
+│     └─ dividesByZero() ✘ / by zero
+└─ JUnit Vintage ✔
+";
+        assert_eq!(
+            test_cases(&compiler, TestRunner::JUnit, junit),
+            [case("addsNumbers()", true), case("dividesByZero()", false)]
+        );
+
+        let go = "\
+=== RUN   TestAdd
+--- PASS: TestAdd (0.00s)
+=== RUN   TestDivide
+    math_test.go:12: got 0, want 1
+--- FAIL: TestDivide (0.00s)
+=== RUN   TestTable
+=== RUN   TestTable/zero
+    --- PASS: TestTable/zero (0.00s)
+--- PASS: TestTable (0.00s)
+FAIL
+FAIL\tcommand-line-arguments\t0.002s
+";
+        assert_eq!(
+            test_cases(&compiler, TestRunner::GoTest, go),
+            [case("TestAdd", true), case("TestDivide", false), case("TestTable/zero", true), case("TestTable", true)]
+        );
+
+        let jest = "\
+FAIL ./math.test.js
+  math
+    ✓ adds numbers (2 ms)
+    ✕ divides by zero (1 ms)
+    √ handles negatives
+";
+        assert_eq!(
+            test_cases(&compiler, TestRunner::Jest, jest),
+            [case("adds numbers", true), case("divides by zero", false), case("handles negatives", true)]
+        );
+
+        let rust = "\
+running 2 tests
+test tests::adds ... ok
+test tests::divides ... FAILED
+";
+        assert_eq!(
+            test_cases(&compiler, TestRunner::RustTest, rust),
+            [case("tests::adds", true), case("tests::divides", false)]
+        );
+    }
+
+    #[test]
+    fn junit_report_escapes_names_and_output() {
+        let compiler = LexiCompiler::new().unwrap();
+        let results = [
+            TestResult {
+                file: "tests/a&b.test.lxi".to_string(),
+                framework: "jest",
+                passed: false,
+                cases: vec![case("keeps <T>", true), case("quotes \"x\"", false)],
+                output: "expected <1> & got \"2\"".to_string(),
+                duration: std::time::Duration::ZERO,
+            },
+            TestResult {
+                file: "tests/broken.test.lxi".to_string(),
+                framework: "pytest",
+                passed: false,
+                cases: Vec::new(),
+                output: "SyntaxError: <stdin>".to_string(),
+                duration: std::time::Duration::ZERO,
+            },
+        ];
+
+        let xml = compiler.build_junit_report(&results);
+        assert!(xml.contains("<testsuites name=\"lexi\" tests=\"3\" failures=\"2\" time=\"0.000\">\n"));
+        assert!(xml.contains("  <testsuite name=\"tests/a&amp;b.test.lxi\" tests=\"2\" failures=\"1\" time=\"0.000\">\n"));
+        assert!(xml.contains("    <testcase classname=\"tests/a&amp;b.test.lxi\" name=\"keeps &lt;T&gt;\"/>\n"));
+        assert!(xml.contains("    <testcase classname=\"tests/a&amp;b.test.lxi\" name=\"quotes &quot;x&quot;\">\n      <failure message=\"test failed\"/>\n"));
+        assert!(xml.contains("    <system-out>expected &lt;1&gt; &amp; got &quot;2&quot;</system-out>\n"));
+        assert!(xml.contains("      <failure message=\"pytest tests did not run\">SyntaxError: &lt;stdin&gt;</failure>\n"));
+    }
+
+    #[test]
+    fn few_shot_examples_pair_by_name_and_keep_the_closest_three() {
+        let dir = temp_dir("few-shot");
+        let python = dir.join("python");
+        fs::create_dir_all(&python).unwrap();
+        let examples = [
+            ("parse_csv", "Parse a CSV file into rows of fields"),
+            ("csv_write", "Write rows to a CSV file"),
+            ("csv_count", "Count the lines in a CSV file"),
+            ("csv_header", "Read the header row of a CSV file"),
+            ("send_email", "Send an email over SMTP"),
+        ];
+        for (stem, description) in examples {
+            fs::write(python.join(format!("{}.lxi", stem)), format!("{}\n", description)).unwrap();
+            fs::write(python.join(format!("{}.py", stem)), format!("def {}():\n    pass\n\n", stem)).unwrap();
+        }
+        // Neither half of a pair on its own is an example
+        fs::write(python.join("orphan.lxi"), "Parse a CSV file into rows\n").unwrap();
+        fs::write(python.join("notes.py"), "# Parse a CSV file into rows\n").unwrap();
+        fs::create_dir_all(dir.join("sql-sqlite")).unwrap();
+        fs::write(dir.join("sql-sqlite").join("users.lxi"), "List the users").unwrap();
+        fs::write(dir.join("sql-sqlite").join("users.sql"), "SELECT * FROM users;").unwrap();
+
+        let project = serde_json::from_value(serde_json::json!({"examplesDir": dir})).unwrap();
+        let compiler = LexiCompiler::with_project(project).unwrap();
+
+        let loaded = compiler.load_few_shot_examples("python").unwrap();
+        let stems: Vec<&str> = loaded.iter().map(|example| example.code.split(['(', ' ']).nth(1).unwrap()).collect();
+        assert_eq!(stems, ["csv_count", "csv_header", "csv_write", "parse_csv", "send_email"]);
+        assert_eq!(loaded[3].description, "Parse a CSV file into rows of fields");
+        assert_eq!(loaded[3].code, "def parse_csv():\n    pass");
+        assert_eq!(compiler.load_few_shot_examples("sql:sqlite").unwrap()[0].code, "SELECT * FROM users;");
+        assert!(compiler.load_few_shot_examples("go").unwrap().is_empty());
+
+        let blocks = compiler.parse_blocks("Parse a CSV file into rows\n");
+        let selected: Vec<&str> = compiler
+            .select_few_shot_examples(&loaded, &blocks)
+            .iter()
+            .map(|example| example.description.as_str())
+            .collect();
+        assert_eq!(selected.len(), MAX_FEW_SHOT_EXAMPLES);
+        assert_eq!(selected, ["Parse a CSV file into rows of fields", "Write rows to a CSV file", "Count the lines in a CSV file"]);
+    }
+
+    #[test]
+    fn csharp_runner_separates_program_arguments() {
+        let compiler = LexiCompiler::new().unwrap();
+        let runner = &compiler.target("csharp").unwrap().runners[0];
+        let steps = compiler.expand_command(runner, std::path::Path::new("build/app.cs")).unwrap();
+        assert_eq!(steps, [["dotnet", "run", "build/app.cs", "--"]]);
+    }
+}